@@ -0,0 +1,846 @@
+use crate::{Candle, CandleSource, Period, Timestamp};
+use std::fmt;
+use std::ops::ControlFlow;
+
+/// Quantities closer to zero than this count as no position, so that the
+/// rounding of fractional quantities doesn't leave a trade open.
+const EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    /// The sign of a change in position on this side.
+    fn sign(self) -> f64 {
+        match self {
+            Side::Buy => 1.0,
+            Side::Sell => -1.0,
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderKind {
+    /// Fills at the open of the next candle.
+    Market,
+    /// Fills at this price or better once a candle trades there, and stays
+    /// working until then or until cancelled.
+    Limit(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Order {
+    pub side: Side,
+    pub quantity: f64,
+    pub kind: OrderKind,
+}
+
+impl Order {
+    pub fn market_buy(quantity: f64) -> Order {
+        Order {
+            side: Side::Buy,
+            quantity,
+            kind: OrderKind::Market,
+        }
+    }
+
+    pub fn market_sell(quantity: f64) -> Order {
+        Order {
+            side: Side::Sell,
+            quantity,
+            kind: OrderKind::Market,
+        }
+    }
+
+    pub fn limit_buy(quantity: f64, price: f64) -> Order {
+        Order {
+            side: Side::Buy,
+            quantity,
+            kind: OrderKind::Limit(price),
+        }
+    }
+
+    pub fn limit_sell(quantity: f64, price: f64) -> Order {
+        Order {
+            side: Side::Sell,
+            quantity,
+            kind: OrderKind::Limit(price),
+        }
+    }
+
+    /// The price the order fills at during the candle, if it does.
+    fn fill_price(&self, candle: &Candle, slippage: f64) -> Option<f64> {
+        match (self.kind, self.side) {
+            (OrderKind::Market, side) => Some(candle.open * (1.0 + side.sign() * slippage)),
+            (OrderKind::Limit(limit), Side::Buy) if candle.low <= limit => {
+                Some(candle.open.min(limit))
+            }
+            (OrderKind::Limit(limit), Side::Sell) if candle.high >= limit => {
+                Some(candle.open.max(limit))
+            }
+            (OrderKind::Limit(_), _) => None,
+        }
+    }
+}
+
+/// Identifies an order placed with the broker, to cancel it or match it to
+/// its fill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OrderId(pub u64);
+
+/// An order executed, in full.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub order: OrderId,
+    pub time: Timestamp,
+    pub side: Side,
+    pub quantity: f64,
+    pub price: f64,
+    pub fee: f64,
+}
+
+/// A round trip, from the first fill taking the position away from flat to
+/// the fill bringing it back. A fill reversing the position closes one trade
+/// and opens another with what is left of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    /// `Buy` for a long position and `Sell` for a short one.
+    pub side: Side,
+    /// The quantity bought or sold to open the position, in total.
+    pub quantity: f64,
+    pub entry_time: Timestamp,
+    /// Average price of the fills opening the position.
+    pub entry_price: f64,
+    pub exit_time: Timestamp,
+    /// Average price of the fills closing it.
+    pub exit_price: f64,
+    pub fees: f64,
+    /// Profit or loss after fees.
+    pub pnl: f64,
+}
+
+/// A trade still open.
+#[derive(Debug, Clone)]
+struct OpenTrade {
+    side: Side,
+    entry_time: Timestamp,
+    entered: f64,
+    entry_value: f64,
+    exited: f64,
+    exit_value: f64,
+    fees: f64,
+}
+
+/// The strategy's account: its cash and position, and the orders it has
+/// placed that haven't filled yet.
+#[derive(Debug, Clone)]
+pub struct Broker {
+    cash: f64,
+    position: f64,
+    price: f64,
+    orders: Vec<(OrderId, Order)>,
+    next_id: u64,
+    fee: f64,
+    slippage: f64,
+    open: Option<OpenTrade>,
+    fills: Vec<Fill>,
+    trades: Vec<Trade>,
+}
+
+impl Broker {
+    /// Places an order, to be considered from the next candle on.
+    ///
+    /// Panics unless the quantity is positive.
+    pub fn submit(&mut self, order: Order) -> OrderId {
+        assert!(order.quantity > 0.0, "orders need a positive quantity");
+        let id = OrderId(self.next_id);
+        self.next_id += 1;
+        self.orders.push((id, order));
+        id
+    }
+
+    /// Withdraws an order, returning false if it has already filled.
+    pub fn cancel(&mut self, id: OrderId) -> bool {
+        let count = self.orders.len();
+        self.orders.retain(|(order, _)| *order != id);
+        self.orders.len() < count
+    }
+
+    pub fn cancel_all(&mut self) {
+        self.orders.clear();
+    }
+
+    /// Orders waiting to be filled, oldest first.
+    pub fn orders(&self) -> &[(OrderId, Order)] {
+        &self.orders
+    }
+
+    /// Quantity held, below zero when short.
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn cash(&self) -> f64 {
+        self.cash
+    }
+
+    /// Cash plus the position valued at the last close.
+    pub fn equity(&self) -> f64 {
+        self.cash + self.position * self.price
+    }
+
+    /// Fills the orders that trade during the candle, in the order they
+    /// were placed.
+    fn execute(&mut self, candle: &Candle) {
+        let slippage = self.slippage;
+        let (filled, working) = self
+            .orders
+            .drain(..)
+            .partition::<Vec<_>, _>(|(_, order)| order.fill_price(candle, slippage).is_some());
+        self.orders = working;
+        for (id, order) in filled {
+            let price = order.fill_price(candle, slippage).unwrap_or_default();
+            self.fill(Fill {
+                order: id,
+                time: candle.time,
+                side: order.side,
+                quantity: order.quantity,
+                price,
+                fee: order.quantity * price * self.fee,
+            });
+        }
+    }
+
+    fn fill(&mut self, fill: Fill) {
+        let sign = fill.side.sign();
+        self.cash -= sign * fill.quantity * fill.price + fill.fee;
+
+        // whatever goes against the position closes it first
+        let mut quantity = fill.quantity;
+        if self.position * sign < 0.0 {
+            let closing = quantity.min(self.position.abs());
+            if let Some(open) = &mut self.open {
+                open.exited += closing;
+                open.exit_value += closing * fill.price;
+                open.fees += fill.fee * closing / fill.quantity;
+            }
+            self.position += sign * closing;
+            quantity -= closing;
+            if self.position.abs() < EPSILON {
+                self.position = 0.0;
+                if let Some(open) = self.open.take() {
+                    self.trades.push(close_trade(open, fill.time));
+                }
+            }
+        }
+        if quantity < EPSILON {
+            self.fills.push(fill);
+            return;
+        }
+        let open = self.open.get_or_insert(OpenTrade {
+            side: fill.side,
+            entry_time: fill.time,
+            entered: 0.0,
+            entry_value: 0.0,
+            exited: 0.0,
+            exit_value: 0.0,
+            fees: 0.0,
+        });
+        open.entered += quantity;
+        open.entry_value += quantity * fill.price;
+        open.fees += fill.fee * quantity / fill.quantity;
+        self.position += sign * quantity;
+        self.fills.push(fill);
+    }
+}
+
+fn close_trade(open: OpenTrade, time: Timestamp) -> Trade {
+    let gross = match open.side {
+        Side::Buy => open.exit_value - open.entry_value,
+        Side::Sell => open.entry_value - open.exit_value,
+    };
+    Trade {
+        side: open.side,
+        quantity: open.entered,
+        entry_time: open.entry_time,
+        entry_price: open.entry_value / open.entered,
+        exit_time: time,
+        exit_price: open.exit_value / open.exited,
+        fees: open.fees,
+        pnl: gross - open.fees,
+    }
+}
+
+/// Trading rules, told of each candle as it closes.
+pub trait Strategy {
+    /// Called with each candle once it has closed, to place or cancel
+    /// orders for the candles to come.
+    fn on_candle(&mut self, candle: &Candle, broker: &mut Broker);
+}
+
+impl<F: FnMut(&Candle, &mut Broker)> Strategy for F {
+    fn on_candle(&mut self, candle: &Candle, broker: &mut Broker) {
+        self(candle, broker)
+    }
+}
+
+/// Settings of a backtest, which replays candles into a trading strategy
+/// and simulates the orders it places, reporting how it would have done.
+///
+/// The strategy sees each candle once it has closed and can only act on the
+/// next one: its orders are filled against the candles that follow, so it
+/// never trades on prices it couldn't yet have known. Market orders fill at
+/// the next open, made worse by the slippage, and limit orders once a
+/// candle's range reaches their price. Every fill pays a fee on its value.
+///
+/// There's a single market and no margin checks: cash may go below zero to
+/// buy and the position below zero to sell short.
+///
+/// ```
+/// # use candle::{Backtest, Broker, Candle, Order, Period};
+/// let candles: Vec<Candle> = [100.0, 110.0, 121.0]
+///     .iter()
+///     .map(|&price| Candle {
+///         open: price,
+///         close: price,
+///         high: price,
+///         low: price,
+///         ..Candle::default()
+///     })
+///     .collect();
+/// // buy one at the first opportunity and hold it
+/// let mut strategy = |_: &Candle, broker: &mut Broker| {
+///     if broker.position() == 0.0 && broker.orders().is_empty() {
+///         broker.submit(Order::market_buy(1.0));
+///     }
+/// };
+/// let report = Backtest::new(Period::M1, 1000.0).run(candles, &mut strategy);
+/// assert_eq!(11.0, report.pnl());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Backtest {
+    period: Period,
+    cash: f64,
+    fee: f64,
+    slippage: f64,
+}
+
+impl Backtest {
+    /// Replays the candles of the period, starting with the cash given and
+    /// without fees or slippage.
+    pub fn new(period: Period, cash: f64) -> Backtest {
+        Backtest {
+            period,
+            cash,
+            fee: 0.0,
+            slippage: 0.0,
+        }
+    }
+
+    /// The fee charged on each fill, as a fraction of its value: 0.001 for
+    /// a tenth of a percent.
+    pub fn fee(mut self, fee: f64) -> Backtest {
+        self.fee = fee;
+        self
+    }
+
+    /// How much worse than the open market orders fill, as a fraction of
+    /// the price.
+    pub fn slippage(mut self, slippage: f64) -> Backtest {
+        self.slippage = slippage;
+        self
+    }
+
+    /// Replays the candles, which must come in time order. Candles of other
+    /// periods are skipped, so a source holding several can be replayed as
+    /// it is.
+    pub fn run<I, S>(&self, candles: I, strategy: &mut S) -> Report
+    where
+        I: IntoIterator<Item = Candle>,
+        S: Strategy + ?Sized,
+    {
+        let mut replay = Replay::new(self);
+        for candle in candles {
+            replay.step(&candle, strategy);
+        }
+        replay.report()
+    }
+
+    /// Replays the candles of a source as they are read.
+    pub fn run_source<C, S>(&self, source: &C, strategy: &mut S) -> Result<Report, C::Error>
+    where
+        C: CandleSource + ?Sized,
+        S: Strategy + ?Sized,
+    {
+        let mut replay = Replay::new(self);
+        source.for_each(&mut |candle| {
+            replay.step(&candle, strategy);
+            ControlFlow::Continue(())
+        })?;
+        Ok(replay.report())
+    }
+}
+
+/// A backtest under way.
+struct Replay {
+    period: Period,
+    initial: f64,
+    broker: Broker,
+    equity: Vec<(Timestamp, f64)>,
+}
+
+impl Replay {
+    fn new(backtest: &Backtest) -> Replay {
+        Replay {
+            period: backtest.period,
+            initial: backtest.cash,
+            broker: Broker {
+                cash: backtest.cash,
+                position: 0.0,
+                price: 0.0,
+                orders: Vec::new(),
+                next_id: 0,
+                fee: backtest.fee,
+                slippage: backtest.slippage,
+                open: None,
+                fills: Vec::new(),
+                trades: Vec::new(),
+            },
+            equity: Vec::new(),
+        }
+    }
+
+    fn step<S: Strategy + ?Sized>(&mut self, candle: &Candle, strategy: &mut S) {
+        if candle.period != self.period {
+            return;
+        }
+        self.broker.execute(candle);
+        self.broker.price = candle.close;
+        self.equity.push((candle.time, self.broker.equity()));
+        strategy.on_candle(candle, &mut self.broker);
+    }
+
+    fn report(self) -> Report {
+        Report {
+            period: self.period,
+            initial_equity: self.initial,
+            equity: self.equity,
+            position: self.broker.position,
+            fills: self.broker.fills,
+            trades: self.broker.trades,
+        }
+    }
+}
+
+/// The outcome of a backtest. A position still open at the end counts at
+/// the last close in the equity, but not in the trades.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub period: Period,
+    pub initial_equity: f64,
+    /// Equity at the close of each candle.
+    pub equity: Vec<(Timestamp, f64)>,
+    /// The position left at the end.
+    pub position: f64,
+    pub fills: Vec<Fill>,
+    /// The round trips completed, in the order they closed.
+    pub trades: Vec<Trade>,
+}
+
+impl Report {
+    pub fn final_equity(&self) -> f64 {
+        self.equity.last().map_or(self.initial_equity, |&(_, e)| e)
+    }
+
+    /// Profit or loss over the whole backtest, after fees.
+    pub fn pnl(&self) -> f64 {
+        self.final_equity() - self.initial_equity
+    }
+
+    /// The profit or loss as a fraction of the initial equity.
+    pub fn total_return(&self) -> f64 {
+        self.pnl() / self.initial_equity
+    }
+
+    /// The largest fall of the equity from a high, as a fraction of that
+    /// high.
+    pub fn max_drawdown(&self) -> f64 {
+        let mut high = self.initial_equity;
+        let mut drawdown: f64 = 0.0;
+        for &(_, equity) in &self.equity {
+            high = high.max(equity);
+            if high > 0.0 {
+                drawdown = drawdown.max((high - equity) / high);
+            }
+        }
+        drawdown
+    }
+
+    /// The annualized Sharpe ratio of the returns from one candle to the
+    /// next, taking the risk-free rate as zero and a year as 365 days since
+    /// the market never closes. `None` without at least two returns, or if
+    /// they never vary. `None` as well if the equity ever falls to zero or
+    /// below, as the account is wiped out and returns on it mean nothing.
+    pub fn sharpe(&self) -> Option<f64> {
+        let wiped_out = self.equity.iter().any(|&(_, equity)| equity <= 0.0);
+        if wiped_out || self.initial_equity <= 0.0 {
+            return None;
+        }
+        let mut previous = self.initial_equity;
+        let returns: Vec<f64> = self
+            .equity
+            .iter()
+            .map(|&(_, equity)| {
+                let r = equity / previous - 1.0;
+                previous = equity;
+                r
+            })
+            .collect();
+        if returns.len() < 2 {
+            return None;
+        }
+        let n = returns.len() as f64;
+        let mean = returns.iter().sum::<f64>() / n;
+        let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        let per_year = 365.0 * Period::D1.millis() as f64 / self.period.millis() as f64;
+        match variance > 0.0 {
+            true => Some(mean / variance.sqrt() * per_year.sqrt()),
+            false => None,
+        }
+    }
+
+    /// The fraction of trades that made money, or `None` without trades.
+    pub fn win_rate(&self) -> Option<f64> {
+        let won = self.trades.iter().filter(|t| t.pnl > 0.0).count();
+        match self.trades.is_empty() {
+            true => None,
+            false => Some(won as f64 / self.trades.len() as f64),
+        }
+    }
+}
+
+/// Writes a summary of the results followed by the trades.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |value: Option<f64>| match value {
+            Some(v) => format!("{:.2}%", v * 100.0),
+            None => "-".to_string(),
+        };
+        match (self.equity.first(), self.equity.last()) {
+            (Some((start, _)), Some((end, _))) => writeln!(
+                f,
+                "candles       {} of {} from {} to {}",
+                self.equity.len(),
+                self.period,
+                start,
+                end
+            )?,
+            _ => writeln!(f, "candles       none of {}", self.period)?,
+        }
+        writeln!(
+            f,
+            "equity        {:.2} to {:.2}",
+            self.initial_equity,
+            self.final_equity()
+        )?;
+        writeln!(
+            f,
+            "pnl           {:.2} ({})",
+            self.pnl(),
+            percent(Some(self.total_return()))
+        )?;
+        writeln!(f, "max drawdown  {}", percent(Some(self.max_drawdown())))?;
+        match self.sharpe() {
+            Some(sharpe) => writeln!(f, "sharpe        {:.2}", sharpe)?,
+            None => writeln!(f, "sharpe        -")?,
+        }
+        writeln!(
+            f,
+            "trades        {}, {} won",
+            self.trades.len(),
+            percent(self.win_rate())
+        )?;
+        for trade in &self.trades {
+            writeln!(
+                f,
+                "  {:4} {} {} @ {:.2} -> {} @ {:.2}  fees {:.2}  pnl {:.2}",
+                trade.side,
+                trade.quantity,
+                trade.entry_time,
+                trade.entry_price,
+                trade.exit_time,
+                trade.exit_price,
+                trade.fees,
+                trade.pnl
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{assert_near, candle, periods_since_start};
+
+    /// Hourly candles starting at `START` from open, high, low and close.
+    fn candles(prices: &[(f64, f64, f64, f64)]) -> Vec<Candle> {
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, &(open, high, low, close))| Candle {
+                high,
+                low,
+                open,
+                close,
+                volume: 1.0,
+                quote_volume: close,
+                ..candle(Period::H1, i as i64)
+            })
+            .collect()
+    }
+
+    /// Candles that open where the last one closed, with a range of one
+    /// either side.
+    fn closes(closes: &[f64]) -> Vec<Candle> {
+        let mut open = closes[0];
+        let prices: Vec<(f64, f64, f64, f64)> = closes
+            .iter()
+            .map(|&close| {
+                let candle = (open, open.max(close) + 1.0, open.min(close) - 1.0, close);
+                open = close;
+                candle
+            })
+            .collect();
+        candles(&prices)
+    }
+
+    /// Places the orders given for each candle, by its index.
+    fn scripted(script: Vec<(usize, Order)>) -> impl FnMut(&Candle, &mut Broker) {
+        let mut i = 0;
+        move |_, broker| {
+            for (_, order) in script.iter().filter(|(at, _)| *at == i) {
+                broker.submit(*order);
+            }
+            i += 1;
+        }
+    }
+
+    #[test]
+    fn market_orders_fill_at_next_open() {
+        let candles = candles(&[
+            (100.0, 101.0, 99.0, 100.0),
+            (102.0, 110.0, 101.0, 108.0),
+            (108.0, 109.0, 104.0, 105.0),
+        ]);
+        let mut strategy = scripted(vec![(0, Order::market_buy(2.0))]);
+        let report = Backtest::new(Period::H1, 1000.0)
+            .fee(0.001)
+            .slippage(0.01)
+            .run(candles, &mut strategy);
+
+        let fill = &report.fills[0];
+        assert_eq!(
+            (OrderId(0), Side::Buy, 2.0),
+            (fill.order, fill.side, fill.quantity)
+        );
+        assert_eq!("2017-07-10T01:00:00Z", fill.time.to_string());
+        assert_near(103.02, fill.price);
+        assert_near(0.20604, fill.fee);
+
+        // cash 1000 - 206.04 - 0.20604, then the two held at the close
+        let equity: Vec<f64> = report.equity.iter().map(|&(_, e)| e).collect();
+        assert_near(1000.0, equity[0]);
+        assert_near(1009.75396, equity[1]);
+        assert_near(1003.75396, equity[2]);
+        assert_eq!(2.0, report.position);
+        assert!(report.trades.is_empty());
+    }
+
+    #[test]
+    fn limit_orders_wait_for_their_price() {
+        let candles = candles(&[
+            (100.0, 101.0, 99.0, 100.0),
+            (100.0, 101.0, 97.0, 98.0),
+            (98.0, 99.0, 94.0, 95.0),
+            (90.0, 92.0, 89.0, 91.0),
+            (91.0, 106.0, 90.0, 104.0),
+        ]);
+        let mut strategy = scripted(vec![
+            (0, Order::limit_buy(1.0, 95.0)),
+            (0, Order::limit_buy(1.0, 93.0)),
+            (2, Order::limit_sell(2.0, 105.0)),
+        ]);
+        let report = Backtest::new(Period::H1, 1000.0)
+            .slippage(0.5)
+            .run(candles, &mut strategy);
+        let fills: Vec<(Side, f64, i64)> = report
+            .fills
+            .iter()
+            .map(|f| (f.side, f.price, periods_since_start(f.time, Period::H1)))
+            .collect();
+        // the first at its limit, the second at the open gapping below it
+        // and the sale at its limit, none of them slipping
+        assert_eq!(
+            vec![
+                (Side::Buy, 95.0, 2),
+                (Side::Buy, 90.0, 3),
+                (Side::Sell, 105.0, 4)
+            ],
+            fills
+        );
+        assert_eq!(1, report.trades.len());
+        assert_near(25.0, report.pnl());
+    }
+
+    #[test]
+    fn cancelled_orders_never_fill() {
+        let mut placed = None;
+        let mut strategy = |candle: &Candle, broker: &mut Broker| match placed {
+            None => placed = Some(broker.submit(Order::limit_buy(1.0, 50.0))),
+            Some(id) if candle.close > 100.0 => {
+                assert!(broker.cancel(id));
+                assert!(!broker.cancel(id));
+                assert!(broker.orders().is_empty());
+            }
+            Some(_) => {}
+        };
+        let report =
+            Backtest::new(Period::H1, 100.0).run(closes(&[100.0, 101.0, 40.0]), &mut strategy);
+        assert!(report.fills.is_empty());
+        assert_eq!(100.0, report.final_equity());
+    }
+
+    #[test]
+    fn trade_ledger() {
+        // buys 1 at 100 and 1 at 110, then sells 3 at 120 to close the long
+        // and go short 1, bought back at 130
+        let candles = closes(&[100.0, 100.0, 110.0, 120.0, 130.0, 130.0]);
+        let mut strategy = scripted(vec![
+            (0, Order::market_buy(1.0)),
+            (2, Order::market_buy(1.0)),
+            (3, Order::market_sell(3.0)),
+            (4, Order::market_buy(1.0)),
+        ]);
+        let report = Backtest::new(Period::H1, 1000.0)
+            .fee(0.01)
+            .run(candles, &mut strategy);
+
+        let trades = &report.trades;
+        assert_eq!(2, trades.len());
+        let long = &trades[0];
+        assert_eq!((Side::Buy, 2.0), (long.side, long.quantity));
+        assert_near(105.0, long.entry_price);
+        assert_near(120.0, long.exit_price);
+        // 1 and 1.1 buying, and two thirds of the 3.6 selling
+        assert_near(4.5, long.fees);
+        assert_near(25.5, long.pnl);
+
+        let short = &trades[1];
+        assert_eq!((Side::Sell, 1.0), (short.side, short.quantity));
+        assert_eq!(long.exit_time, short.entry_time);
+        assert_near(120.0, short.entry_price);
+        assert_near(130.0, short.exit_price);
+        assert_near(2.5, short.fees);
+        assert_near(-12.5, short.pnl);
+
+        assert_eq!(0.0, report.position);
+        assert_near(13.0, report.pnl());
+        assert_eq!(Some(0.5), report.win_rate());
+    }
+
+    #[test]
+    fn drawdown_and_sharpe() {
+        // hold one unit through 100, 120, 90 and 110
+        let candles = closes(&[100.0, 100.0, 120.0, 90.0, 110.0]);
+        let mut strategy = scripted(vec![(0, Order::market_buy(1.0))]);
+        let report = Backtest::new(Period::H1, 100.0).run(candles, &mut strategy);
+        assert_near(10.0, report.pnl());
+        assert_near(0.1, report.total_return());
+        assert_near(0.25, report.max_drawdown());
+
+        let returns = [0.0, 0.0, 0.2, -0.25, 110.0 / 90.0 - 1.0];
+        let mean = returns.iter().sum::<f64>() / 5.0;
+        let sd = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / 4.0).sqrt();
+        assert_near(
+            mean / sd * (365.0f64 * 24.0).sqrt(),
+            report.sharpe().unwrap(),
+        );
+
+        let mut idle = |_: &Candle, _: &mut Broker| {};
+        let idle = Backtest::new(Period::H1, 100.0).run(closes(&[1.0, 2.0]), &mut idle);
+        assert_eq!(None, idle.sharpe());
+        assert_eq!(None, idle.win_rate());
+        assert_eq!(0.0, idle.max_drawdown());
+    }
+
+    #[test]
+    fn no_sharpe_once_wiped_out() {
+        // short one from 100, left with 100 - 150 then 100 - 200
+        let candles = closes(&[100.0, 100.0, 250.0, 300.0]);
+        let mut strategy = scripted(vec![(0, Order::market_sell(1.0))]);
+        let report = Backtest::new(Period::H1, 100.0).run(candles, &mut strategy);
+        assert_near(-100.0, report.final_equity());
+        assert_eq!(None, report.sharpe());
+
+        // wiped out on the last close, with no candle after it
+        let candles = closes(&[100.0, 100.0, 110.0, 200.0]);
+        let mut strategy = scripted(vec![(0, Order::market_sell(1.0))]);
+        let report = Backtest::new(Period::H1, 100.0).run(candles, &mut strategy);
+        assert_near(0.0, report.final_equity());
+        assert_eq!(None, report.sharpe());
+    }
+
+    #[test]
+    fn replays_one_period_from_a_source() {
+        let mut mixed = closes(&[100.0, 100.0, 110.0]);
+        mixed.insert(
+            1,
+            Candle {
+                period: Period::D1,
+                close: 1.0,
+                open: 1.0,
+                ..mixed[0].clone()
+            },
+        );
+        let mut seen = Vec::new();
+        let mut strategy = |candle: &Candle, broker: &mut Broker| {
+            seen.push(candle.close);
+            if seen.len() == 1 {
+                broker.submit(Order::market_buy(1.0));
+            }
+        };
+        let report = Backtest::new(Period::H1, 100.0)
+            .run_source(&mixed, &mut strategy)
+            .unwrap();
+        assert_eq!(vec![100.0, 100.0, 110.0], seen);
+        assert_near(10.0, report.pnl());
+
+        let text = report.to_string();
+        assert!(text.starts_with(
+            "candles       3 of 1h from 2017-07-10T00:00:00Z to 2017-07-10T02:00:00Z\n\
+             equity        100.00 to 110.00\n\
+             pnl           10.00 (10.00%)\n"
+        ));
+        assert!(text.ends_with("trades        0, - won\n"), "{}", text);
+    }
+
+    #[test]
+    #[should_panic(expected = "orders need a positive quantity")]
+    fn rejects_empty_orders() {
+        let mut strategy = scripted(vec![(0, Order::market_sell(0.0))]);
+        Backtest::new(Period::H1, 100.0).run(closes(&[1.0]), &mut strategy);
+    }
+}
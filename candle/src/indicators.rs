@@ -0,0 +1,548 @@
+use crate::{Candle, Period, Timestamp};
+use std::collections::VecDeque;
+
+/// A value computed from the candles seen so far. Indicators take candles
+/// one at a time and return a value once they have seen enough of them to
+/// be meaningful, so they work the same over candles loaded into memory and
+/// candles streamed from the database.
+///
+/// ```
+/// # use candle::{Candle, Indicator, Sma};
+/// let mut sma = Sma::new(3);
+/// let values: Vec<f64> = [1.0, 2.0, 3.0, 4.0]
+///     .iter()
+///     .map(|&close| Candle { close, ..Candle::default() })
+///     .filter_map(|candle| sma.update(&candle))
+///     .collect();
+/// assert_eq!(vec![2.0, 3.0], values);
+/// ```
+///
+/// The indicators computed from closing prices alone also take plain values
+/// with `push`, so that they can be applied to other series.
+pub trait Indicator {
+    type Output;
+
+    /// Takes the next candle, returning the value of the indicator once it
+    /// is warmed up and `None` before then.
+    fn update(&mut self, candle: &Candle) -> Option<Self::Output>;
+}
+
+/// Simple moving average: the mean of the last `len` closes.
+#[derive(Debug, Clone)]
+pub struct Sma {
+    len: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+    /// Values taken off the sum since it was last added up afresh.
+    removed: usize,
+}
+
+impl Sma {
+    /// Panics if `len` is zero, as do the other indicators.
+    pub fn new(len: usize) -> Sma {
+        assert!(len > 0, "indicators need a length of at least one");
+        Sma {
+            len,
+            window: VecDeque::with_capacity(len + 1),
+            sum: 0.0,
+            removed: 0,
+        }
+    }
+
+    pub fn push(&mut self, value: f64) -> Option<f64> {
+        self.window.push_back(value);
+        self.sum += value;
+        if self.window.len() > self.len {
+            let oldest = self.window.pop_front().unwrap_or_default();
+            self.removed += 1;
+            // each subtraction leaves some rounding error behind, so the sum
+            // is added up again once the whole window has been replaced
+            if self.removed == self.len {
+                self.removed = 0;
+                self.sum = self.window.iter().sum();
+            } else {
+                self.sum -= oldest;
+            }
+        }
+        match self.window.len() == self.len {
+            true => Some(self.sum / self.len as f64),
+            false => None,
+        }
+    }
+}
+
+impl Indicator for Sma {
+    type Output = f64;
+
+    fn update(&mut self, candle: &Candle) -> Option<f64> {
+        self.push(candle.close)
+    }
+}
+
+/// Exponential moving average, weighting each close by `2 / (len + 1)`. It
+/// starts from the simple average of the first `len` closes.
+#[derive(Debug, Clone)]
+pub struct Ema {
+    alpha: f64,
+    /// Averages the first closes until the EMA takes over.
+    seed: Option<Sma>,
+    value: f64,
+}
+
+impl Ema {
+    pub fn new(len: usize) -> Ema {
+        Ema {
+            alpha: 2.0 / (len as f64 + 1.0),
+            seed: Some(Sma::new(len)),
+            value: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, value: f64) -> Option<f64> {
+        match &mut self.seed {
+            Some(seed) => {
+                self.value = seed.push(value)?;
+                self.seed = None;
+            }
+            None => self.value += self.alpha * (value - self.value),
+        }
+        Some(self.value)
+    }
+}
+
+impl Indicator for Ema {
+    type Output = f64;
+
+    fn update(&mut self, candle: &Candle) -> Option<f64> {
+        self.push(candle.close)
+    }
+}
+
+/// Wilder's smoothing, an average where each new value has a weight of
+/// `1 / len`, starting from the simple average of the first `len` values.
+#[derive(Debug, Clone)]
+struct Wilder {
+    len: f64,
+    seed: Option<Sma>,
+    value: f64,
+}
+
+impl Wilder {
+    fn new(len: usize) -> Wilder {
+        Wilder {
+            len: len as f64,
+            seed: Some(Sma::new(len)),
+            value: 0.0,
+        }
+    }
+
+    fn push(&mut self, value: f64) -> Option<f64> {
+        match &mut self.seed {
+            Some(seed) => {
+                self.value = seed.push(value)?;
+                self.seed = None;
+            }
+            None => self.value = (self.value * (self.len - 1.0) + value) / self.len,
+        }
+        Some(self.value)
+    }
+}
+
+/// Relative strength index: how the average gain of the last `len` changes
+/// in close compares to the average loss, from 0 when the price only fell to
+/// 100 when it only rose, with the averages smoothed as Wilder did. The first
+/// value comes with the close after the first `len`.
+#[derive(Debug, Clone)]
+pub struct Rsi {
+    previous: Option<f64>,
+    gains: Wilder,
+    losses: Wilder,
+}
+
+impl Rsi {
+    pub fn new(len: usize) -> Rsi {
+        Rsi {
+            previous: None,
+            gains: Wilder::new(len),
+            losses: Wilder::new(len),
+        }
+    }
+
+    pub fn push(&mut self, value: f64) -> Option<f64> {
+        let change = value - self.previous.replace(value)?;
+        let gain = self.gains.push(change.max(0.0));
+        let loss = self.losses.push((-change).max(0.0));
+        Some(match (gain?, loss?) {
+            (gain, loss) if gain + loss == 0.0 => 50.0,
+            (gain, loss) => 100.0 * gain / (gain + loss),
+        })
+    }
+}
+
+impl Indicator for Rsi {
+    type Output = f64;
+
+    fn update(&mut self, candle: &Candle) -> Option<f64> {
+        self.push(candle.close)
+    }
+}
+
+/// A value of the MACD.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MacdValue {
+    /// The fast EMA less the slow one.
+    pub macd: f64,
+    /// The EMA of the MACD line.
+    pub signal: f64,
+    /// The MACD line less the signal line.
+    pub histogram: f64,
+}
+
+/// Moving average convergence divergence: the gap between a fast and a slow
+/// EMA of the closes, and an EMA of that gap as a signal line. Its first
+/// value needs `slow + signal - 1` closes.
+#[derive(Debug, Clone)]
+pub struct Macd {
+    fast: Ema,
+    slow: Ema,
+    signal: Ema,
+}
+
+impl Macd {
+    /// The lengths of the fast, slow and signal EMAs, commonly 12, 26 and 9.
+    pub fn new(fast: usize, slow: usize, signal: usize) -> Macd {
+        Macd {
+            fast: Ema::new(fast),
+            slow: Ema::new(slow),
+            signal: Ema::new(signal),
+        }
+    }
+
+    pub fn push(&mut self, value: f64) -> Option<MacdValue> {
+        let (fast, slow) = (self.fast.push(value), self.slow.push(value));
+        let macd = fast? - slow?;
+        let signal = self.signal.push(macd)?;
+        Some(MacdValue {
+            macd,
+            signal,
+            histogram: macd - signal,
+        })
+    }
+}
+
+/// The usual 12, 26 and 9.
+impl Default for Macd {
+    fn default() -> Macd {
+        Macd::new(12, 26, 9)
+    }
+}
+
+impl Indicator for Macd {
+    type Output = MacdValue;
+
+    fn update(&mut self, candle: &Candle) -> Option<MacdValue> {
+        self.push(candle.close)
+    }
+}
+
+/// A value of the Bollinger Bands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bands {
+    pub upper: f64,
+    /// The simple moving average.
+    pub middle: f64,
+    pub lower: f64,
+}
+
+/// Bollinger Bands: the simple moving average of the last `len` closes,
+/// with bands `width` standard deviations of those closes above and below.
+#[derive(Debug, Clone)]
+pub struct Bollinger {
+    sma: Sma,
+    width: f64,
+}
+
+impl Bollinger {
+    /// Commonly 20 closes and 2 standard deviations.
+    pub fn new(len: usize, width: f64) -> Bollinger {
+        Bollinger {
+            sma: Sma::new(len),
+            width,
+        }
+    }
+
+    pub fn push(&mut self, value: f64) -> Option<Bands> {
+        let middle = self.sma.push(value)?;
+        // from the window rather than running sums of squares, which lose
+        // precision when the prices are large and the spread small
+        let window = &self.sma.window;
+        let variance =
+            window.iter().map(|v| (v - middle).powi(2)).sum::<f64>() / window.len() as f64;
+        let offset = self.width * variance.sqrt();
+        Some(Bands {
+            upper: middle + offset,
+            middle,
+            lower: middle - offset,
+        })
+    }
+}
+
+impl Indicator for Bollinger {
+    type Output = Bands;
+
+    fn update(&mut self, candle: &Candle) -> Option<Bands> {
+        self.push(candle.close)
+    }
+}
+
+/// Average true range: Wilder's average of the last `len` true ranges, a
+/// candle's range widened to take in the close before it.
+#[derive(Debug, Clone)]
+pub struct Atr {
+    close: Option<f64>,
+    average: Wilder,
+}
+
+impl Atr {
+    pub fn new(len: usize) -> Atr {
+        Atr {
+            close: None,
+            average: Wilder::new(len),
+        }
+    }
+}
+
+impl Indicator for Atr {
+    type Output = f64;
+
+    fn update(&mut self, candle: &Candle) -> Option<f64> {
+        let range = match self.close.replace(candle.close) {
+            Some(close) => candle.high.max(close) - candle.low.min(close),
+            None => candle.high - candle.low,
+        };
+        self.average.push(range)
+    }
+}
+
+/// Volume weighted average price: the average of each candle's typical
+/// price, the mean of its high, low and close, weighted by its volume. It
+/// has a value once some volume has traded.
+#[derive(Debug, Clone, Default)]
+pub struct Vwap {
+    session: Option<Period>,
+    start: Option<Timestamp>,
+    value: f64,
+    volume: f64,
+}
+
+impl Vwap {
+    /// Averages over every candle seen.
+    pub fn new() -> Vwap {
+        Vwap::default()
+    }
+
+    /// Starts over with each bucket of the period, such as every day at
+    /// midnight UTC for `Period::D1`.
+    pub fn session(period: Period) -> Vwap {
+        Vwap {
+            session: Some(period),
+            ..Vwap::default()
+        }
+    }
+}
+
+impl Indicator for Vwap {
+    type Output = f64;
+
+    fn update(&mut self, candle: &Candle) -> Option<f64> {
+        if let Some(period) = self.session {
+            let start = candle.time.align(period);
+            if self.start.replace(start) != Some(start) {
+                self.value = 0.0;
+                self.volume = 0.0;
+            }
+        }
+        let typical = (candle.high + candle.low + candle.close) / 3.0;
+        self.value += typical * candle.volume;
+        self.volume += candle.volume;
+        match self.volume > 0.0 {
+            true => Some(self.value / self.volume),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{assert_close, candle};
+
+    /// Closes of the 10 day EMA example in StockCharts' ChartSchool.
+    const CLOSES: [f64; 30] = [
+        22.27, 22.19, 22.08, 22.17, 22.18, 22.13, 22.23, 22.43, 22.24, 22.29, 22.15, 22.39, 22.38,
+        22.61, 23.36, 24.05, 23.75, 23.83, 23.95, 23.63, 23.82, 23.87, 23.65, 23.19, 23.10, 23.33,
+        22.68, 23.10, 22.40, 22.17,
+    ];
+
+    fn closes(values: &[f64]) -> Vec<Candle> {
+        values
+            .iter()
+            .map(|&close| Candle {
+                close,
+                ..Candle::default()
+            })
+            .collect()
+    }
+
+    /// The values of the indicator over the candles, leaving out those
+    /// while it warms up.
+    fn run<T: Indicator>(mut indicator: T, candles: &[Candle]) -> Vec<T::Output> {
+        candles.iter().filter_map(|c| indicator.update(c)).collect()
+    }
+
+    #[test]
+    fn sma() {
+        let values = run(Sma::new(10), &closes(&CLOSES));
+        assert_eq!(21, values.len());
+        assert_close(
+            &[22.22, 22.21, 22.23, 22.26, 22.30, 22.42, 22.61, 22.77],
+            &values[..8],
+            0.005,
+        );
+        assert_eq!(vec![1.5, 2.5], run(Sma::new(2), &closes(&[1.0, 2.0, 3.0])));
+    }
+
+    #[test]
+    fn sma_does_not_drift() {
+        // 1e16 + 1 rounds back to 1e16, so subtracting 1e16 again loses the 1
+        let mut sma = Sma::new(2);
+        let values: Vec<Option<f64>> = [1e16, 1.0, 1.0, 1.0, 1.0]
+            .iter()
+            .map(|&v| sma.push(v))
+            .collect();
+        assert_eq!(Some(1.0), values[4]);
+    }
+
+    #[test]
+    fn ema() {
+        // as rounded in the example's table
+        let expected = [
+            22.22, 22.21, 22.24, 22.27, 22.33, 22.52, 22.80, 22.97, 23.13, 23.28, 23.34, 23.43,
+            23.51, 23.53, 23.47, 23.40, 23.39, 23.26, 23.23, 23.08, 22.92,
+        ];
+        assert_close(&expected, &run(Ema::new(10), &closes(&CLOSES)), 0.005);
+    }
+
+    #[test]
+    fn rsi() {
+        // Wilder's 14 day RSI as worked in StockCharts' ChartSchool
+        let candles = closes(&[
+            44.3389, 44.0902, 44.1497, 43.6124, 44.3278, 44.8264, 45.0955, 45.4245, 45.8433,
+            46.0826, 45.8931, 46.0328, 45.614, 46.282, 46.282, 46.0028, 46.0328, 46.4116, 46.2222,
+            45.6439, 46.2122, 46.2521, 45.7137, 46.4515, 45.7835, 45.3548, 44.0288, 44.1783,
+            44.2181, 44.5672, 43.4205, 42.6628, 43.1314,
+        ]);
+        let expected = [
+            70.53, 66.32, 66.55, 69.41, 66.36, 57.97, 62.93, 63.26, 56.06, 62.38, 54.71, 50.42,
+            39.99, 41.46, 41.87, 45.46, 37.30, 33.08, 37.77,
+        ];
+        assert_close(&expected, &run(Rsi::new(14), &candles), 0.005);
+
+        let rising = run(Rsi::new(2), &closes(&[5.0, 5.0, 5.0, 6.0, 7.0]));
+        assert_eq!(vec![50.0, 100.0, 100.0], rising);
+    }
+
+    #[test]
+    fn macd() {
+        // checked against the EMAs worked out in full
+        let values = run(Macd::new(5, 10, 4), &closes(&CLOSES));
+        assert_eq!(CLOSES.len() - 12, values.len());
+        let lines = |f: fn(&MacdValue) -> f64| values[..3].iter().map(f).collect::<Vec<_>>();
+        assert_close(&[0.0487, 0.0845, 0.2126], &lines(|v| v.macd), 0.00005);
+        assert_close(&[0.0396, 0.0576, 0.1196], &lines(|v| v.signal), 0.00005);
+        assert_close(&[0.0091, 0.0269, 0.0930], &lines(|v| v.histogram), 0.00005);
+        assert!(run(Macd::default(), &closes(&CLOSES)).is_empty());
+    }
+
+    #[test]
+    fn bollinger() {
+        let values = run(Bollinger::new(20, 2.0), &closes(&CLOSES));
+        assert_eq!(11, values.len());
+        assert_close(
+            &[24.1261, 22.7155, 21.3049],
+            &[values[0].upper, values[0].middle, values[0].lower],
+            0.00005,
+        );
+        assert_close(
+            &[24.3939, 22.8770, 21.3601],
+            &[values[2].upper, values[2].middle, values[2].lower],
+            0.00005,
+        );
+    }
+
+    #[test]
+    fn atr() {
+        // the start of Wilder's 14 day ATR example in StockCharts' ChartSchool
+        let prices = [
+            (48.70, 47.79, 48.16),
+            (48.72, 48.14, 48.61),
+            (48.90, 48.39, 48.75),
+            (48.87, 48.37, 48.63),
+            (48.82, 48.24, 48.74),
+            (49.05, 48.64, 49.03),
+            (49.20, 48.94, 49.07),
+            (49.35, 48.86, 49.32),
+            (49.92, 49.50, 49.91),
+            (50.19, 49.87, 50.13),
+            (50.12, 49.20, 49.53),
+            (49.66, 48.90, 49.50),
+            (49.88, 49.43, 49.75),
+            (50.19, 49.73, 50.03),
+            (50.36, 49.26, 50.31),
+        ];
+        let candles: Vec<Candle> = prices
+            .iter()
+            .map(|&(high, low, close)| Candle {
+                high,
+                low,
+                close,
+                ..Candle::default()
+            })
+            .collect();
+        assert_close(&[0.55, 0.59], &run(Atr::new(14), &candles), 0.005);
+        assert_close(
+            &[0.616, 0.5748, 0.5118],
+            &run(Atr::new(5), &candles)[..3],
+            0.00005,
+        );
+    }
+
+    #[test]
+    fn vwap() {
+        let hour = |n: i64, high: f64, low: f64, close: f64, volume: f64| Candle {
+            high,
+            low,
+            close,
+            volume,
+            ..candle(Period::H1, n)
+        };
+        let candles = [
+            hour(22, 12.0, 9.0, 9.0, 0.0),
+            hour(23, 12.0, 9.0, 12.0, 10.0),
+            hour(24, 21.0, 18.0, 21.0, 30.0),
+            hour(25, 15.0, 15.0, 15.0, 10.0),
+        ];
+        assert_close(&[11.0, 17.75, 17.2], &run(Vwap::new(), &candles), 1e-9);
+        assert_close(
+            &[11.0, 20.0, 18.75],
+            &run(Vwap::session(Period::D1), &candles),
+            1e-9,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "indicators need a length of at least one")]
+    fn zero_length() {
+        Rsi::new(0);
+    }
+}
@@ -0,0 +1,79 @@
+//! Market data shared by the candle experiments: the `Candle` model and the
+//! sources candles can be read from. The MySQL source is behind the default
+//! `mysql` feature, so crates that read the database some other way can
+//! depend on the model alone. The `sqlx` feature lets candles be fetched
+//! with `sqlx::query_as` instead.
+
+use std::fmt;
+
+mod backtest;
+mod indicators;
+#[cfg(feature = "mysql")]
+mod mysql_source;
+mod resample;
+mod source;
+#[cfg(feature = "sqlx")]
+mod sqlx_row;
+#[cfg(test)]
+mod test_util;
+mod time;
+
+pub use backtest::{
+    Backtest, Broker, Fill, Order, OrderId, OrderKind, Report, Side, Strategy, Trade,
+};
+pub use indicators::{Atr, Bands, Bollinger, Ema, Indicator, Macd, MacdValue, Rsi, Sma, Vwap};
+#[cfg(feature = "mysql")]
+pub use mysql_source::{
+    load_candles_direct, parse_candle, stream_candle_cont, stream_candles, MySqlSource,
+    DEFAULT_TABLE,
+};
+pub use resample::{Resample, Resampler};
+pub use source::CandleSource;
+pub use time::{ParsePeriodError, Period, Timestamp};
+
+/// Trading over one period: the prices at its open and close, the highest
+/// and lowest prices in between, and the volume traded.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Candle {
+    pub period: Period,
+    /// Start of the period.
+    pub time: Timestamp,
+    pub high: f64,
+    pub low: f64,
+    pub open: f64,
+    pub close: f64,
+    /// Volume traded in the base currency.
+    pub volume: f64,
+    /// Volume traded in the quote currency.
+    pub quote_volume: f64,
+}
+
+/// Prints the fields as a tuple in the order period, time, open, high, low,
+/// close, volume and quote volume:
+///
+/// ```
+/// # use candle::{Candle, Period, Timestamp};
+/// let candle = Candle {
+///     period: Period::H1,
+///     time: Timestamp::from_seconds(1_500_000_000),
+///     open: 2.5,
+///     ..Candle::default()
+/// };
+/// assert_eq!("(1h, 2017-07-14T02:40:00Z, 2.5, 0, 0, 0, 0, 0)", candle.to_string());
+/// ```
+impl fmt::Display for Candle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({}, {}, {}, {}, {}, {}, {}, {})",
+            self.period,
+            self.time,
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.volume,
+            self.quote_volume
+        )
+    }
+}
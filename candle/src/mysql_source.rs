@@ -0,0 +1,101 @@
+use crate::{Candle, CandleSource, Period, Timestamp};
+use mysql::prelude::*;
+use mysql::{Pool, Result, Row};
+use std::ops::ControlFlow;
+use std::sync::mpsc::SyncSender;
+
+/// Table read by the loaders that don't take one.
+pub const DEFAULT_TABLE: &str = "candle.binance_btc_usdt";
+
+/// Candles stored in a MySQL table whose columns are, in order: period and
+/// unix, both in seconds, then high, low, open, close, volume and
+/// quote_volume.
+#[derive(Clone)]
+pub struct MySqlSource {
+    pool: Pool,
+    table: String,
+}
+
+impl MySqlSource {
+    /// Reads `DEFAULT_TABLE`.
+    pub fn new(pool: Pool) -> MySqlSource {
+        MySqlSource::with_table(pool, DEFAULT_TABLE)
+    }
+
+    pub fn with_table(pool: Pool, table: &str) -> MySqlSource {
+        MySqlSource {
+            pool,
+            table: table.to_string(),
+        }
+    }
+
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    fn select(&self) -> String {
+        format!("select * from {}", self.table)
+    }
+}
+
+impl CandleSource for MySqlSource {
+    type Error = mysql::Error;
+
+    /// Iterates the rows of the result set as the server sends them. After a
+    /// break the rows left are skipped without being parsed, as the
+    /// connection still has to drain them before going back to the pool.
+    fn for_each(&self, on_candle: &mut dyn FnMut(Candle) -> ControlFlow<()>) -> Result<()> {
+        let mut conn = self.pool.get_conn()?;
+        let mut result = conn.query_iter(self.select())?;
+        if let Some(result_set) = result.next_set() {
+            for row in result_set? {
+                if on_candle(parse_candle(&row?)?).is_break() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads every candle of `DEFAULT_TABLE` into memory.
+pub fn load_candles_direct(pool: &Pool) -> Result<Vec<Candle>> {
+    MySqlSource::new(pool.clone()).load()
+}
+
+/// Sends every candle of `DEFAULT_TABLE` over the channel as it is read.
+pub fn stream_candles(pool: &Pool, sender: &SyncSender<Candle>) -> Result<()> {
+    MySqlSource::new(pool.clone()).stream(sender)
+}
+
+/// Calls `on_row` with every candle of `DEFAULT_TABLE` as it is read, in
+/// continuation passing style.
+pub fn stream_candle_cont<F>(pool: &Pool, on_row: &mut F) -> Result<()>
+where
+    F: FnMut(Candle),
+{
+    MySqlSource::new(pool.clone()).for_each(&mut |candle| {
+        on_row(candle);
+        ControlFlow::Continue(())
+    })
+}
+
+/// Builds a candle from a row of the table. Missing or mistyped prices and
+/// volumes are left at zero, but a period that isn't one of `Period`'s is a
+/// `FromRowError`.
+pub fn parse_candle(row: &Row) -> Result<Candle> {
+    let period = row
+        .get(0)
+        .and_then(Period::from_seconds)
+        .ok_or_else(|| mysql::Error::FromRowError(row.clone()))?;
+    Ok(Candle {
+        period,
+        time: Timestamp::from_seconds(row.get(1).unwrap_or_default()),
+        high: row.get(2).unwrap_or_default(),
+        low: row.get(3).unwrap_or_default(),
+        open: row.get(4).unwrap_or_default(),
+        close: row.get(5).unwrap_or_default(),
+        volume: row.get(6).unwrap_or_default(),
+        quote_volume: row.get(7).unwrap_or_default(),
+    })
+}
@@ -0,0 +1,385 @@
+use crate::{Candle, Period, Timestamp};
+use std::collections::VecDeque;
+
+/// Turns candles into candles of a longer period. Implemented for every
+/// iterator of candles, so it applies as well to candles loaded into memory
+/// as to those received from a streaming source:
+///
+/// ```
+/// # use candle::{Candle, Period, Resample, Timestamp};
+/// let minutes = (0..120).map(|m| Candle {
+///     period: Period::M1,
+///     time: Timestamp::from_seconds(1_500_000_000 + m * 60),
+///     close: m as f64,
+///     volume: 1.0,
+///     ..Candle::default()
+/// });
+/// let hours: Vec<Candle> = minutes.resample(Period::M1, Period::H1).collect();
+/// assert_eq!(3, hours.len());
+/// assert_eq!("2017-07-14T02:00:00Z", hours[0].time.to_string());
+/// assert_eq!((19.0, 20.0), (hours[0].close, hours[0].volume));
+/// ```
+pub trait Resample: Iterator<Item = Candle> + Sized {
+    /// Combines the candles of period `from` into candles of period `to`,
+    /// skipping candles of any other period, so that a stream holding
+    /// several periods can be resampled as it is. The candles should come in
+    /// time order: one no later than the candle of `from` before it, such as
+    /// a duplicate, is skipped rather than counted twice or starting its
+    /// bucket over.
+    ///
+    /// Panics unless each bucket of `to` is made up of whole buckets of
+    /// `from`.
+    fn resample(self, from: Period, to: Period) -> Resampler<Self> {
+        assert!(
+            to.is_multiple_of(from),
+            "can't resample {} candles to {}",
+            from,
+            to
+        );
+        Resampler {
+            candles: self,
+            from,
+            to,
+            bucket: None,
+            latest: None,
+            close: None,
+            ready: VecDeque::new(),
+            complete_only: false,
+            fill_gaps: false,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Candle>> Resample for I {}
+
+/// Iterator returned by `Resample::resample`.
+///
+/// Each candle covers a bucket of the longer period: it opens at the open of
+/// the first candle in the bucket and closes at the close of the last, with
+/// the highest high and lowest low of them and the sum of their volumes. A
+/// bucket is yielded once a candle of a later bucket arrives or the candles
+/// run out.
+///
+/// Buckets missing some of their candles, such as those at either end of
+/// the data or around downtime at the exchange, are made of those there
+/// are, unless `complete_only` drops them. Buckets with no candles at all
+/// are skipped, unless `fill_gaps` fills them in.
+pub struct Resampler<I> {
+    candles: I,
+    from: Period,
+    to: Period,
+    /// The bucket being built and how many candles it has.
+    bucket: Option<(Candle, i64)>,
+    /// Time of the last candle taken into a bucket.
+    latest: Option<Timestamp>,
+    /// Close of the last bucket queued, which gaps after it are priced at.
+    close: Option<f64>,
+    /// Candles finished but not yet yielded, when gaps are filled.
+    ready: VecDeque<Candle>,
+    complete_only: bool,
+    fill_gaps: bool,
+}
+
+impl<I> Resampler<I> {
+    /// Only yields buckets holding every candle of the shorter period.
+    pub fn complete_only(mut self) -> Resampler<I> {
+        self.complete_only = true;
+        self
+    }
+
+    /// Yields a candle for buckets without any data, priced flat at the
+    /// close before them and with no volume, so that the candles are evenly
+    /// spaced. Buckets dropped by `complete_only` count as gaps.
+    pub fn fill_gaps(mut self) -> Resampler<I> {
+        self.fill_gaps = true;
+        self
+    }
+
+    /// Queues the bucket, and when filling gaps a flat candle for each
+    /// bucket between it and the one starting at `next`, if any.
+    fn finish(&mut self, (candle, count): (Candle, i64), next: Option<Timestamp>) {
+        let complete = count >= self.to.millis() / self.from.millis();
+        let mut time = candle.time;
+        if complete || !self.complete_only {
+            time = time.next(self.to);
+            self.close = Some(candle.close);
+            self.ready.push_back(candle);
+        }
+        let (next, close) = match (next, self.close) {
+            (Some(next), Some(close)) if self.fill_gaps => (next, close),
+            _ => return,
+        };
+        while time < next {
+            self.ready.push_back(Candle {
+                period: self.to,
+                time,
+                high: close,
+                low: close,
+                open: close,
+                close,
+                volume: 0.0,
+                quote_volume: 0.0,
+            });
+            time = time.next(self.to);
+        }
+    }
+}
+
+impl<I: Iterator<Item = Candle>> Iterator for Resampler<I> {
+    type Item = Candle;
+
+    fn next(&mut self) -> Option<Candle> {
+        loop {
+            if let Some(candle) = self.ready.pop_front() {
+                return Some(candle);
+            }
+            let candle = match self.candles.next() {
+                Some(candle) => candle,
+                None => {
+                    let bucket = self.bucket.take()?;
+                    self.finish(bucket, None);
+                    continue;
+                }
+            };
+            if candle.period != self.from || self.latest >= Some(candle.time) {
+                continue;
+            }
+            self.latest = Some(candle.time);
+            let start = candle.time.align(self.to);
+            match &mut self.bucket {
+                Some((bucket, count)) if bucket.time == start => {
+                    bucket.high = bucket.high.max(candle.high);
+                    bucket.low = bucket.low.min(candle.low);
+                    bucket.close = candle.close;
+                    bucket.volume += candle.volume;
+                    bucket.quote_volume += candle.quote_volume;
+                    *count += 1;
+                }
+                _ => {
+                    let bucket = Candle {
+                        period: self.to,
+                        time: start,
+                        ..candle
+                    };
+                    if let Some(previous) = self.bucket.replace((bucket, 1)) {
+                        self.finish(previous, Some(start));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, periods_since_start, START};
+
+    /// A candle of the period starting `n` periods after `START`, priced
+    /// `open` to `close` with the range widened by one.
+    fn candle(period: Period, n: i64, open: f64, close: f64) -> Candle {
+        Candle {
+            high: open.max(close) + 1.0,
+            low: open.min(close) - 1.0,
+            open,
+            close,
+            volume: 2.0,
+            quote_volume: 2.0 * close,
+            ..test_util::candle(period, n)
+        }
+    }
+
+    fn resample(candles: Vec<Candle>, from: Period, to: Period) -> Vec<Candle> {
+        candles.into_iter().resample(from, to).collect()
+    }
+
+    #[test]
+    fn combines_prices_and_volumes() {
+        let minutes = vec![
+            candle(Period::M1, 0, 10.0, 12.0),
+            candle(Period::M1, 1, 12.0, 20.0),
+            candle(Period::M1, 2, 20.0, 5.0),
+            candle(Period::M1, 3, 5.0, 7.0),
+            candle(Period::M1, 4, 7.0, 8.0),
+            candle(Period::M1, 5, 8.0, 9.0),
+        ];
+        let candles = resample(minutes, Period::M1, Period::M5);
+        assert_eq!(
+            vec![
+                Candle {
+                    period: Period::M5,
+                    time: Timestamp(START),
+                    high: 21.0,
+                    low: 4.0,
+                    open: 10.0,
+                    close: 8.0,
+                    volume: 10.0,
+                    quote_volume: 104.0,
+                },
+                Candle {
+                    period: Period::M5,
+                    time: Timestamp(1_499_645_100_000),
+                    ..candle(Period::M1, 5, 8.0, 9.0)
+                },
+            ],
+            candles
+        );
+    }
+
+    #[test]
+    fn skips_other_periods() {
+        let candles = vec![
+            candle(Period::D1, 0, 1.0, 2.0),
+            candle(Period::H1, 0, 10.0, 11.0),
+            candle(Period::M1, 0, 100.0, 101.0),
+            candle(Period::H1, 1, 11.0, 12.0),
+            candle(Period::D1, 1, 2.0, 3.0),
+        ];
+        let candles = resample(candles, Period::H1, Period::H4);
+        assert_eq!(1, candles.len());
+        assert_eq!(
+            (10.0, 12.0, 4.0),
+            (candles[0].open, candles[0].close, candles[0].volume)
+        );
+    }
+
+    #[test]
+    fn partial_buckets() {
+        // the first hour lacks its first quarter and the last its second half
+        let quarters = vec![
+            candle(Period::M15, 1, 1.0, 2.0),
+            candle(Period::M15, 2, 2.0, 3.0),
+            candle(Period::M15, 3, 3.0, 4.0),
+            candle(Period::M15, 4, 4.0, 5.0),
+            candle(Period::M15, 5, 5.0, 6.0),
+            candle(Period::M15, 6, 6.0, 7.0),
+            candle(Period::M15, 7, 7.0, 8.0),
+            candle(Period::M15, 8, 8.0, 9.0),
+            candle(Period::M15, 9, 9.0, 10.0),
+        ];
+        let opens = |candles: Vec<Candle>| -> Vec<f64> { candles.iter().map(|c| c.open).collect() };
+        assert_eq!(
+            vec![1.0, 4.0, 8.0],
+            opens(resample(quarters.clone(), Period::M15, Period::H1))
+        );
+        let complete: Vec<Candle> = quarters
+            .into_iter()
+            .resample(Period::M15, Period::H1)
+            .complete_only()
+            .collect();
+        assert_eq!(vec![4.0], opens(complete));
+    }
+
+    #[test]
+    fn gaps() {
+        let hours = vec![
+            candle(Period::H1, 0, 1.0, 2.0),
+            candle(Period::H1, 13, 2.0, 3.0),
+            candle(Period::H1, 14, 3.0, 4.0),
+        ];
+        let times = |candles: &[Candle]| -> Vec<i64> {
+            candles
+                .iter()
+                .map(|c| periods_since_start(c.time, Period::H4))
+                .collect()
+        };
+
+        let skipped = resample(hours.clone(), Period::H1, Period::H4);
+        assert_eq!(vec![0, 3], times(&skipped));
+
+        let filled: Vec<Candle> = hours
+            .into_iter()
+            .resample(Period::H1, Period::H4)
+            .fill_gaps()
+            .collect();
+        assert_eq!(vec![0, 1, 2, 3], times(&filled));
+        let flat = &filled[1];
+        assert_eq!(
+            (2.0, 2.0, 2.0, 2.0),
+            (flat.open, flat.high, flat.low, flat.close)
+        );
+        assert_eq!((0.0, 0.0), (flat.volume, flat.quote_volume));
+        assert_eq!(Period::H4, flat.period);
+    }
+
+    #[test]
+    fn dropped_buckets_are_gaps() {
+        // the second hour lacks its last quarter
+        let quarters: Vec<Candle> = [0, 1, 2, 3, 4, 5, 6, 8, 9, 10, 11]
+            .iter()
+            .map(|&n| candle(Period::M15, n, n as f64, n as f64 + 1.0))
+            .collect();
+        let hours: Vec<(i64, f64, f64)> = quarters
+            .into_iter()
+            .resample(Period::M15, Period::H1)
+            .complete_only()
+            .fill_gaps()
+            .map(|c| (periods_since_start(c.time, Period::H1), c.open, c.volume))
+            .collect();
+        assert_eq!(vec![(0, 0.0, 8.0), (1, 4.0, 0.0), (2, 8.0, 8.0)], hours);
+    }
+
+    #[test]
+    fn skips_candles_out_of_order() {
+        let minutes = vec![
+            candle(Period::M1, 3, 3.0, 4.0),
+            candle(Period::M1, 4, 4.0, 5.0),
+            candle(Period::M1, 4, 4.0, 5.0),
+            candle(Period::M1, 2, 2.0, 3.0),
+            candle(Period::M1, 5, 5.0, 6.0),
+            candle(Period::M1, 1, 1.0, 2.0),
+            candle(Period::M1, 6, 6.0, 7.0),
+        ];
+        let candles = resample(minutes, Period::M1, Period::M5);
+        let candles: Vec<(f64, f64, f64)> = candles
+            .iter()
+            .map(|c| (c.open, c.close, c.volume))
+            .collect();
+        assert_eq!(vec![(3.0, 5.0, 4.0), (5.0, 7.0, 4.0)], candles);
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        // from the Sunday before the Monday of `candle` to the Monday after
+        let days: Vec<Candle> = (-1..=7)
+            .map(|n| candle(Period::D1, n, n as f64, n as f64 + 1.0))
+            .collect();
+        let weeks = resample(days, Period::D1, Period::W1);
+        let weeks: Vec<(String, f64, f64)> = weeks
+            .iter()
+            .map(|w| (w.time.to_string(), w.open, w.close))
+            .collect();
+        assert_eq!(
+            vec![
+                ("2017-07-03T00:00:00Z".to_string(), -1.0, 0.0),
+                ("2017-07-10T00:00:00Z".to_string(), 0.0, 7.0),
+                ("2017-07-17T00:00:00Z".to_string(), 7.0, 8.0),
+            ],
+            weeks
+        );
+    }
+
+    #[test]
+    fn composes_with_sources() {
+        use crate::CandleSource;
+        use std::sync::mpsc::sync_channel;
+        use std::thread;
+
+        let minutes: Vec<Candle> = (0..30).map(|n| candle(Period::M1, n, 1.0, 1.0)).collect();
+        let (sender, receiver) = sync_channel(0);
+        let producer = thread::spawn(move || minutes.stream(&sender));
+        let volumes: Vec<f64> = receiver
+            .iter()
+            .resample(Period::M1, Period::M15)
+            .map(|c| c.volume)
+            .collect();
+        producer.join().unwrap().unwrap();
+        assert_eq!(vec![30.0, 30.0], volumes);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't resample 15m candles to 5m")]
+    fn coarser_period_must_be_made_of_finer() {
+        Vec::new().into_iter().resample(Period::M15, Period::M5);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::Candle;
+use std::convert::Infallible;
+use std::ops::ControlFlow;
+use std::sync::mpsc::SyncSender;
+
+/// Somewhere candles can be read from.
+///
+/// Implementations only need to provide `for_each`, which hands over each
+/// candle as it is read. Loading everything into memory and streaming to
+/// another thread are built on top of it, and may be overridden when the
+/// source can do better.
+pub trait CandleSource {
+    type Error;
+
+    /// Calls `on_candle` with each candle in turn, without holding them all
+    /// in memory. Reading stops early once `on_candle` breaks.
+    fn for_each(
+        &self,
+        on_candle: &mut dyn FnMut(Candle) -> ControlFlow<()>,
+    ) -> Result<(), Self::Error>;
+
+    /// Reads every candle into memory.
+    fn load(&self) -> Result<Vec<Candle>, Self::Error> {
+        let mut candles = Vec::new();
+        self.for_each(&mut |candle| {
+            candles.push(candle);
+            ControlFlow::Continue(())
+        })?;
+        Ok(candles)
+    }
+
+    /// Sends each candle over the channel as it is read, for a consumer on
+    /// another thread. Reading stops once the receiver hangs up.
+    fn stream(&self, sender: &SyncSender<Candle>) -> Result<(), Self::Error> {
+        self.for_each(&mut |candle| match sender.send(candle) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        })
+    }
+}
+
+/// Candles already in memory, handed out in order. Useful in tests and for
+/// replaying data loaded from elsewhere.
+impl CandleSource for Vec<Candle> {
+    type Error = Infallible;
+
+    fn for_each(
+        &self,
+        on_candle: &mut dyn FnMut(Candle) -> ControlFlow<()>,
+    ) -> Result<(), Infallible> {
+        let _ = self.iter().cloned().try_for_each(on_candle);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Period, Timestamp};
+    use std::cell::Cell;
+    use std::sync::mpsc::sync_channel;
+    use std::thread;
+
+    fn candles() -> Vec<Candle> {
+        (0..3)
+            .map(|i| Candle {
+                period: Period::M1,
+                time: Timestamp::from_seconds(1_500_000_000 + i * 60),
+                close: i as f64,
+                ..Candle::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn load_and_for_each() {
+        let source = candles();
+        assert_eq!(source, source.load().unwrap());
+
+        let mut seen = Vec::new();
+        source
+            .for_each(&mut |candle| {
+                seen.push(candle.time.seconds());
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(vec![1_500_000_000, 1_500_000_060, 1_500_000_120], seen);
+    }
+
+    #[test]
+    fn for_each_stops_on_break() {
+        let mut seen = Vec::new();
+        candles()
+            .for_each(&mut |candle| {
+                seen.push(candle.close);
+                if seen.len() < 2 {
+                    ControlFlow::Continue(())
+                } else {
+                    ControlFlow::Break(())
+                }
+            })
+            .unwrap();
+        assert_eq!(vec![0.0, 1.0], seen);
+    }
+
+    #[test]
+    fn stream_to_another_thread() {
+        let (sender, receiver) = sync_channel(0);
+        let producer = thread::spawn(move || candles().stream(&sender));
+        let closes: Vec<f64> = receiver.iter().map(|candle| candle.close).collect();
+        producer.join().unwrap().unwrap();
+        assert_eq!(vec![0.0, 1.0, 2.0], closes);
+    }
+
+    /// Counts the candles taken from the source, to check that reading stops.
+    struct Counted(Vec<Candle>, Cell<usize>);
+
+    impl CandleSource for Counted {
+        type Error = Infallible;
+
+        fn for_each(
+            &self,
+            on_candle: &mut dyn FnMut(Candle) -> ControlFlow<()>,
+        ) -> Result<(), Infallible> {
+            self.0.for_each(&mut |candle| {
+                self.1.set(self.1.get() + 1);
+                on_candle(candle)
+            })
+        }
+    }
+
+    #[test]
+    fn stream_after_hang_up() {
+        let (sender, receiver) = sync_channel(3);
+        drop(receiver);
+        let source = Counted(candles(), Cell::new(0));
+        assert!(source.stream(&sender).is_ok());
+        assert_eq!(1, source.1.get());
+    }
+}
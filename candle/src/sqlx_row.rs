@@ -0,0 +1,27 @@
+use crate::{Candle, Period, Timestamp};
+use sqlx::mysql::MySqlRow;
+use sqlx::{FromRow, Row};
+
+/// Reads a row of the candle table, with its columns in the order of
+/// `MySqlSource`, so that `sqlx::query_as::<_, Candle>` works with
+/// `select *`. A period that isn't one of `Period`'s fails to decode.
+impl<'c> FromRow<'c, MySqlRow<'c>> for Candle {
+    fn from_row(row: &MySqlRow<'c>) -> sqlx::Result<Candle> {
+        let seconds: i32 = row.try_get(0)?;
+        let period = Period::from_seconds(i64::from(seconds)).ok_or_else(|| {
+            let message = format!("unknown candle period of {} seconds", seconds);
+            sqlx::Error::Decode(message.into())
+        })?;
+        let unix: i32 = row.try_get(1)?;
+        Ok(Candle {
+            period,
+            time: Timestamp::from_seconds(i64::from(unix)),
+            high: row.try_get(2)?,
+            low: row.try_get(3)?,
+            open: row.try_get(4)?,
+            close: row.try_get(5)?,
+            volume: row.try_get(6)?,
+            quote_volume: row.try_get(7)?,
+        })
+    }
+}
@@ -0,0 +1,33 @@
+//! Fixtures shared by the unit tests.
+
+use crate::{Candle, Period, Timestamp};
+
+/// 2017-07-10, a Monday, which the candles of the tests count from.
+pub const START: i64 = 1_499_644_800_000;
+
+/// A candle of the period starting `n` periods after `START`, with every
+/// price and volume left at zero for the test to fill in.
+pub fn candle(period: Period, n: i64) -> Candle {
+    Candle {
+        period,
+        time: Timestamp(START + n * period.millis()),
+        ..Candle::default()
+    }
+}
+
+/// How many periods after `START` the time is, the reverse of `candle`.
+pub fn periods_since_start(time: Timestamp, period: Period) -> i64 {
+    (time.millis() - START) / period.millis()
+}
+
+pub fn assert_close(expected: &[f64], actual: &[f64], tolerance: f64) {
+    assert_eq!(expected.len(), actual.len(), "{:?}", actual);
+    for (e, a) in expected.iter().zip(actual) {
+        assert!((e - a).abs() <= tolerance, "{:?} != {:?}", expected, actual);
+    }
+}
+
+/// Asserts the values equal but for rounding.
+pub fn assert_near(expected: f64, actual: f64) {
+    assert_close(&[expected], &[actual], 1e-9);
+}
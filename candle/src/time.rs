@@ -0,0 +1,300 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+const MINUTE: i64 = 60_000;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+const WEEK: i64 = 7 * DAY;
+
+/// The epoch fell on a Thursday, so weeks, which start on Monday as they do
+/// at the exchanges, begin three days before it.
+const FIRST_MONDAY: i64 = -3 * DAY;
+
+/// Length of time a candle covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Period {
+    M1,
+    M5,
+    M15,
+    H1,
+    H4,
+    D1,
+    /// A week from Monday at midnight UTC.
+    W1,
+}
+
+impl Period {
+    /// Every period, shortest first.
+    pub const ALL: [Period; 7] = [
+        Period::M1,
+        Period::M5,
+        Period::M15,
+        Period::H1,
+        Period::H4,
+        Period::D1,
+        Period::W1,
+    ];
+
+    pub fn millis(self) -> i64 {
+        match self {
+            Period::M1 => MINUTE,
+            Period::M5 => 5 * MINUTE,
+            Period::M15 => 15 * MINUTE,
+            Period::H1 => HOUR,
+            Period::H4 => 4 * HOUR,
+            Period::D1 => DAY,
+            Period::W1 => WEEK,
+        }
+    }
+
+    pub fn seconds(self) -> i64 {
+        self.millis() / 1000
+    }
+
+    /// The period of the given length, as stored in the database.
+    pub fn from_seconds(seconds: i64) -> Option<Period> {
+        Period::ALL.iter().copied().find(|p| p.seconds() == seconds)
+    }
+
+    /// Whether every bucket of this period is made up of whole buckets of
+    /// the finer one, so that candles of it can be built from candles of
+    /// the finer one.
+    pub fn is_multiple_of(self, finer: Period) -> bool {
+        self.millis() % finer.millis() == 0 && self.offset() % finer.millis() == 0
+    }
+
+    /// Where the buckets are counted from.
+    fn offset(self) -> i64 {
+        match self {
+            Period::W1 => FIRST_MONDAY,
+            _ => 0,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Period::M1 => "1m",
+            Period::M5 => "5m",
+            Period::M15 => "15m",
+            Period::H1 => "1h",
+            Period::H4 => "4h",
+            Period::D1 => "1d",
+            Period::W1 => "1w",
+        }
+    }
+}
+
+/// The shortest period, so that a default candle is a one minute one.
+impl Default for Period {
+    fn default() -> Period {
+        Period::M1
+    }
+}
+
+/// Writes the period the way the exchanges do, such as `15m` or `4h`.
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// Reads a period written as by `Display`.
+///
+/// ```
+/// # use candle::Period;
+/// assert_eq!(Ok(Period::H4), "4h".parse());
+/// assert!("2h".parse::<Period>().is_err());
+/// ```
+impl FromStr for Period {
+    type Err = ParsePeriodError;
+
+    fn from_str(s: &str) -> Result<Period, ParsePeriodError> {
+        Period::ALL
+            .iter()
+            .copied()
+            .find(|p| p.name() == s)
+            .ok_or_else(|| ParsePeriodError(s.to_string()))
+    }
+}
+
+/// The text given for a period isn't one of those known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsePeriodError(String);
+
+impl fmt::Display for ParsePeriodError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = Period::ALL.iter().map(|p| p.name()).collect();
+        write!(
+            f,
+            "unknown period '{}', expected one of {}",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl Error for ParsePeriodError {}
+
+/// An instant in milliseconds since the Unix epoch, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Timestamp(pub i64);
+
+impl Timestamp {
+    pub fn from_seconds(seconds: i64) -> Timestamp {
+        Timestamp(seconds * 1000)
+    }
+
+    pub fn millis(self) -> i64 {
+        self.0
+    }
+
+    /// Whole seconds since the epoch, rounded down.
+    pub fn seconds(self) -> i64 {
+        self.0.div_euclid(1000)
+    }
+
+    /// The start of the bucket of the period holding this instant.
+    ///
+    /// ```
+    /// # use candle::{Period, Timestamp};
+    /// let time = Timestamp::from_seconds(1_500_000_000);
+    /// assert_eq!("2017-07-14T02:40:00Z", time.to_string());
+    /// assert_eq!("2017-07-14T00:00:00Z", time.align(Period::H4).to_string());
+    /// assert_eq!("2017-07-10T00:00:00Z", time.align(Period::W1).to_string());
+    /// ```
+    pub fn align(self, period: Period) -> Timestamp {
+        let offset = period.offset();
+        Timestamp(self.0 - (self.0 - offset).rem_euclid(period.millis()))
+    }
+
+    /// Whether this instant starts a bucket of the period.
+    pub fn is_aligned(self, period: Period) -> bool {
+        self.align(period) == self
+    }
+
+    /// The start of the bucket of the period following the one holding this
+    /// instant.
+    pub fn next(self, period: Period) -> Timestamp {
+        Timestamp(self.align(period).0 + period.millis())
+    }
+}
+
+/// Writes the instant in RFC 3339 form in UTC, with milliseconds only when
+/// there are some: `2017-07-14T02:40:00Z` or `2017-07-14T02:40:00.250Z`.
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days = self.0.div_euclid(DAY);
+        let ms = self.0.rem_euclid(DAY);
+        let (year, month, day) = civil_from_days(days);
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            ms / HOUR,
+            ms % HOUR / MINUTE,
+            ms % MINUTE / 1000
+        )?;
+        match ms % 1000 {
+            0 => write!(f, "Z"),
+            millis => write!(f, ".{:03}Z", millis),
+        }
+    }
+}
+
+/// The date of a day counted from the epoch in the proleptic Gregorian
+/// calendar, after Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // count from 0000-03-01 so that leap days fall at the end of a year
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periods_parse_and_display() {
+        for period in Period::ALL.iter() {
+            assert_eq!(Ok(*period), period.to_string().parse());
+            assert_eq!(Some(*period), Period::from_seconds(period.seconds()));
+        }
+        assert_eq!(86_400, Period::D1.seconds());
+        assert_eq!(None, Period::from_seconds(7200));
+        assert_eq!(
+            "unknown period '1M', expected one of 1m, 5m, 15m, 1h, 4h, 1d, 1w",
+            "1M".parse::<Period>().unwrap_err().to_string()
+        );
+        assert_eq!("15m  ", format!("{:5}", Period::M15));
+    }
+
+    #[test]
+    fn formats_in_utc() {
+        assert_eq!("1970-01-01T00:00:00Z", Timestamp(0).to_string());
+        assert_eq!("1969-12-31T23:59:59.999Z", Timestamp(-1).to_string());
+        assert_eq!(
+            "2038-01-19T03:14:08Z",
+            Timestamp::from_seconds(i64::from(i32::MAX) + 1).to_string()
+        );
+        assert_eq!(
+            "2000-02-29T12:00:00.250Z",
+            Timestamp(951_825_600_250).to_string()
+        );
+        assert_eq!(
+            "2017-03-01T00:00:00Z",
+            Timestamp::from_seconds(1_488_326_400).to_string()
+        );
+    }
+
+    #[test]
+    fn aligns_to_buckets() {
+        let time = Timestamp::from_seconds(1_500_000_420);
+        assert_eq!("2017-07-14T02:47:00Z", time.to_string());
+        let aligned: Vec<String> = Period::ALL
+            .iter()
+            .map(|&p| time.align(p).to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "2017-07-14T02:47:00Z",
+                "2017-07-14T02:45:00Z",
+                "2017-07-14T02:45:00Z",
+                "2017-07-14T02:00:00Z",
+                "2017-07-14T00:00:00Z",
+                "2017-07-14T00:00:00Z",
+                "2017-07-10T00:00:00Z",
+            ],
+            aligned
+        );
+        assert_eq!("2017-07-14T03:00:00Z", time.next(Period::H1).to_string());
+        assert_eq!("2017-07-17T00:00:00Z", time.next(Period::W1).to_string());
+        assert!(time.align(Period::M15).is_aligned(Period::M15));
+        assert!(!time.is_aligned(Period::M5));
+
+        // before the epoch buckets still start on the boundary below
+        assert_eq!(Timestamp(-DAY), Timestamp(-1).align(Period::D1));
+        assert_eq!(
+            "1969-12-29T00:00:00Z",
+            Timestamp(0).align(Period::W1).to_string()
+        );
+    }
+
+    #[test]
+    fn coarser_periods() {
+        assert!(Period::H4.is_multiple_of(Period::M15));
+        assert!(Period::W1.is_multiple_of(Period::D1));
+        assert!(Period::M5.is_multiple_of(Period::M5));
+        assert!(!Period::M5.is_multiple_of(Period::M15));
+    }
+}
@@ -0,0 +1,74 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use minigrep::{search_reader, Finder, Matcher, PrintOptions, Printer, Stats};
+use std::io;
+use std::path::Path;
+
+/// Generates about 16 MiB of text where one line in `every` contains the
+/// needle.
+fn haystack(every: usize) -> String {
+    let mut contents = String::new();
+    let mut n = 0;
+    while contents.len() < 16 << 20 {
+        match n % every {
+            0 => contents.push_str("the quick brown fox jumps over the lazy dog\n"),
+            _ => contents.push_str("lorem ipsum dolor sit amet, consectetur adipiscing elit\n"),
+        }
+        n += 1;
+    }
+    contents
+}
+
+/// Streams the contents through `search_reader` without printing anything,
+/// as the command line does.
+fn search_reader_with(matcher: &Matcher, contents: &str) -> Stats {
+    let mut printer = Printer::new(io::sink(), PrintOptions::default());
+    search_reader(
+        matcher,
+        false,
+        None,
+        Path::new("-"),
+        contents.as_bytes(),
+        &mut printer,
+    )
+    .unwrap()
+}
+
+fn literal(c: &mut Criterion) {
+    let query = "lazy dog";
+    let finder = Finder::new(query).unwrap();
+    // the same query as a regex, which is still matched a line at a time
+    let by_line = Matcher::regex(query, true).unwrap();
+    let by_block = Matcher::literal(query, true);
+
+    let mut group = c.benchmark_group("literal");
+    group.sample_size(10);
+    for every in [10_000, 100, 1] {
+        let contents = haystack(every);
+        group.throughput(Throughput::Bytes(contents.len() as u64));
+        // the original search, checking each line with `str::contains`
+        group.bench_with_input(
+            BenchmarkId::new("line_contains", every),
+            &contents,
+            |b, contents| b.iter(|| contents.lines().filter(|l| l.contains(query)).count()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("search_reader_by_line", every),
+            &contents,
+            |b, contents| b.iter(|| search_reader_with(&by_line, contents)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("search_reader_by_block", every),
+            &contents,
+            |b, contents| b.iter(|| search_reader_with(&by_block, contents)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("finder", every),
+            &contents,
+            |b, contents| b.iter(|| finder.search(contents)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, literal);
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use minigrep::{search_files, Config, Matcher, PrintOptions, Printer};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::thread;
+
+/// Writes a corpus of text files where roughly one line in a hundred matches.
+fn corpus(dir: &std::path::Path, files: usize, lines: usize) -> Vec<PathBuf> {
+    (0..files)
+        .map(|i| {
+            let path = dir.join(format!("{:04}.txt", i));
+            let contents: String = (0..lines)
+                .map(|n| match (i + n) % 100 {
+                    0 => format!("{} the quick brown fox jumps over the lazy dog\n", n),
+                    _ => format!("{} lorem ipsum dolor sit amet, consectetur adipiscing\n", n),
+                })
+                .collect();
+            fs::write(&path, contents).unwrap();
+            path
+        })
+        .collect()
+}
+
+fn parallel(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = corpus(dir.path(), 200, 5_000);
+    let matcher = Matcher::regex(r"qu\w+ brown", true).unwrap();
+    // compare against at least two workers, even on a single CPU
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get()).max(2);
+
+    let mut group = c.benchmark_group("search_files");
+    group.sample_size(10);
+    for jobs in [1, cpus] {
+        let config = Config {
+            jobs,
+            ..Config::default()
+        };
+        group.bench_with_input(BenchmarkId::new("jobs", jobs), &config, |b, config| {
+            b.iter(|| {
+                let options = PrintOptions {
+                    with_path: true,
+                    ..PrintOptions::default()
+                };
+                let mut printer = Printer::new(io::sink(), options);
+                search_files(&paths, &matcher, config, &mut printer).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parallel);
+criterion_main!(benches);
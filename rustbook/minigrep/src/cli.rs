@@ -0,0 +1,1310 @@
+use crate::{Config, OutputMode, Setting, SettingValue, Settings, Source, EXIT_ERROR};
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::PathBuf;
+
+/// What the command line asked minigrep to do.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Command {
+    Search(Config),
+    /// `minigrep index build [DIR]`: create or update the trigram index.
+    IndexBuild(Config),
+    /// `minigrep index search QUERY [DIR]`: search using the index.
+    IndexSearch(Config),
+    /// `--debug-config`: the report of the options in effect and where
+    /// each was set.
+    DebugConfig(String),
+    Help,
+    Version,
+}
+
+/// Reasons a command line can be rejected.
+#[derive(Debug, PartialEq)]
+pub enum ArgsError {
+    /// No query string was given.
+    MissingQuery,
+    /// An option that minigrep does not know about.
+    UnknownOption(String),
+    /// An option that takes a value was last on the command line.
+    MissingValue(String),
+    /// An option that does not take a value was given one with `--opt=value`.
+    UnexpectedValue(String),
+    /// An option value could not be parsed.
+    InvalidValue { option: String, value: String },
+    /// An option was given without another option it depends on.
+    Requires { option: String, required: String },
+    /// Two options were given that can't be used together.
+    Conflicts { option: String, other: String },
+    /// `minigrep index` was followed by something other than `build` or
+    /// `search`, or by nothing.
+    UnknownCommand(String),
+    /// `--profile` named a profile that no configuration file defines.
+    UnknownProfile(String),
+    /// A configuration file couldn't be read or has an invalid setting.
+    Config(String),
+    /// An argument that isn't valid UTF-8.
+    NotUnicode(OsString),
+}
+
+impl ArgsError {
+    /// The exit status for a rejected command line. Like grep, it is the
+    /// same as for any other error.
+    pub fn exit_code(&self) -> i32 {
+        EXIT_ERROR
+    }
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgsError::MissingQuery => write!(f, "Didn't get a query string"),
+            ArgsError::UnknownOption(option) => write!(f, "Unknown option {}", option),
+            ArgsError::MissingValue(option) => write!(f, "Option {} requires a value", option),
+            ArgsError::UnexpectedValue(option) => {
+                write!(f, "Option {} doesn't take a value", option)
+            }
+            ArgsError::InvalidValue { option, value } => {
+                write!(f, "Invalid value '{}' for option {}", value, option)
+            }
+            ArgsError::Requires { option, required } => {
+                write!(f, "Option {} requires {}", option, required)
+            }
+            ArgsError::Conflicts { option, other } => {
+                write!(f, "Option {} can't be used with {}", option, other)
+            }
+            ArgsError::UnknownCommand(command) => write!(
+                f,
+                "Unknown command 'index {}', expected 'index build' or 'index search'",
+                command
+            ),
+            ArgsError::UnknownProfile(name) => {
+                write!(f, "No profile named '{}' in the configuration files", name)
+            }
+            ArgsError::Config(message) => write!(f, "{}", message),
+            ArgsError::NotUnicode(arg) => {
+                write!(f, "Argument '{}' is not valid UTF-8", arg.to_string_lossy())
+            }
+        }
+    }
+}
+
+impl Error for ArgsError {}
+
+/// Every option the parser knows about, independent of how it was spelled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Opt {
+    AfterContext,
+    BeforeContext,
+    ByteOffset,
+    Color,
+    Column,
+    Context,
+    Count,
+    DebugConfig,
+    Decompress,
+    DryRun,
+    Exclude,
+    FilesWithMatches,
+    FilesWithoutMatch,
+    Fuzzy,
+    Help,
+    Hidden,
+    IgnoreCase,
+    InPlace,
+    Include,
+    Invert,
+    Jobs,
+    Json,
+    LineNumber,
+    NoConfig,
+    NoIgnore,
+    Pattern,
+    PatternFile,
+    Profile,
+    Quiet,
+    Regex,
+    Replace,
+    SkipBinary,
+    SmartCase,
+    Version,
+    Watch,
+    Word,
+}
+
+/// Describes one option: its spellings, the name of its value if it takes
+/// one, and the text shown by `--help`.
+struct Spec {
+    short: Option<char>,
+    long: &'static str,
+    value: Option<&'static str>,
+    help: &'static str,
+    opt: Opt,
+}
+
+const SPECS: &[Spec] = &[
+    Spec {
+        short: Some('E'),
+        long: "regex",
+        value: None,
+        help: "Treat the query as a regular expression",
+        opt: Opt::Regex,
+    },
+    Spec {
+        short: Some('e'),
+        long: "regexp",
+        value: Some("PATTERN"),
+        help: "Search for PATTERN; may be repeated to search for several",
+        opt: Opt::Pattern,
+    },
+    Spec {
+        short: Some('f'),
+        long: "file",
+        value: Some("FILE"),
+        help: "Search for the patterns in FILE, one per line",
+        opt: Opt::PatternFile,
+    },
+    Spec {
+        short: Some('i'),
+        long: "ignore-case",
+        value: None,
+        help: "Match case insensitively (also set by CASE_INSENSITIVE)",
+        opt: Opt::IgnoreCase,
+    },
+    Spec {
+        short: Some('S'),
+        long: "smart-case",
+        value: None,
+        help: "Ignore case unless the query contains an uppercase letter",
+        opt: Opt::SmartCase,
+    },
+    Spec {
+        short: Some('w'),
+        long: "word-regexp",
+        value: None,
+        help: "Only match whole words",
+        opt: Opt::Word,
+    },
+    Spec {
+        short: None,
+        long: "fuzzy",
+        value: Some("NUM"),
+        help: "Match text within NUM edits of the query, closest lines first",
+        opt: Opt::Fuzzy,
+    },
+    Spec {
+        short: Some('v'),
+        long: "invert-match",
+        value: None,
+        help: "Select the lines that don't match",
+        opt: Opt::Invert,
+    },
+    Spec {
+        short: Some('c'),
+        long: "count",
+        value: None,
+        help: "Print the number of selected lines in each file",
+        opt: Opt::Count,
+    },
+    Spec {
+        short: Some('l'),
+        long: "files-with-matches",
+        value: None,
+        help: "Print the path of each file with selected lines",
+        opt: Opt::FilesWithMatches,
+    },
+    Spec {
+        short: Some('L'),
+        long: "files-without-match",
+        value: None,
+        help: "Print the path of each file without selected lines",
+        opt: Opt::FilesWithoutMatch,
+    },
+    Spec {
+        short: Some('q'),
+        long: "quiet",
+        value: None,
+        help: "Print nothing; exit 0 on a match, 1 otherwise",
+        opt: Opt::Quiet,
+    },
+    Spec {
+        short: Some('n'),
+        long: "line-number",
+        value: None,
+        help: "Prefix each line with its line number",
+        opt: Opt::LineNumber,
+    },
+    Spec {
+        short: Some('b'),
+        long: "byte-offset",
+        value: None,
+        help: "Prefix each line with its byte offset",
+        opt: Opt::ByteOffset,
+    },
+    Spec {
+        short: None,
+        long: "column",
+        value: None,
+        help: "Prefix matching lines with the column of the first match",
+        opt: Opt::Column,
+    },
+    Spec {
+        short: Some('A'),
+        long: "after-context",
+        value: Some("NUM"),
+        help: "Print NUM lines of context after each match",
+        opt: Opt::AfterContext,
+    },
+    Spec {
+        short: Some('B'),
+        long: "before-context",
+        value: Some("NUM"),
+        help: "Print NUM lines of context before each match",
+        opt: Opt::BeforeContext,
+    },
+    Spec {
+        short: Some('C'),
+        long: "context",
+        value: Some("NUM"),
+        help: "Print NUM lines of context around each match",
+        opt: Opt::Context,
+    },
+    Spec {
+        short: None,
+        long: "color",
+        value: Some("WHEN"),
+        help: "Highlight output: auto, always or never (colours from MINIGREP_COLORS)",
+        opt: Opt::Color,
+    },
+    Spec {
+        short: None,
+        long: "json",
+        value: None,
+        help: "Print results as JSON Lines",
+        opt: Opt::Json,
+    },
+    Spec {
+        short: None,
+        long: "include",
+        value: Some("GLOB"),
+        help: "Only search files matching GLOB (repeatable)",
+        opt: Opt::Include,
+    },
+    Spec {
+        short: None,
+        long: "exclude",
+        value: Some("GLOB"),
+        help: "Skip files and directories matching GLOB (repeatable)",
+        opt: Opt::Exclude,
+    },
+    Spec {
+        short: None,
+        long: "hidden",
+        value: None,
+        help: "Search hidden files and directories",
+        opt: Opt::Hidden,
+    },
+    Spec {
+        short: None,
+        long: "no-ignore",
+        value: None,
+        help: "Don't honour .gitignore, .ignore or .git/info/exclude",
+        opt: Opt::NoIgnore,
+    },
+    Spec {
+        short: Some('I'),
+        long: "skip-binary",
+        value: None,
+        help: "Skip binary files instead of reporting matches",
+        opt: Opt::SkipBinary,
+    },
+    Spec {
+        short: Some('r'),
+        long: "replace",
+        value: Some("TEMPLATE"),
+        help: "Print lines with each match replaced by TEMPLATE ($1 names a group)",
+        opt: Opt::Replace,
+    },
+    Spec {
+        short: None,
+        long: "in-place",
+        value: None,
+        help: "Rewrite files with the replacements instead of printing",
+        opt: Opt::InPlace,
+    },
+    Spec {
+        short: None,
+        long: "dry-run",
+        value: None,
+        help: "Show the changes --in-place would make as a unified diff",
+        opt: Opt::DryRun,
+    },
+    Spec {
+        short: Some('z'),
+        long: "search-zip",
+        value: None,
+        help: "Search inside gzip, zstd, bzip2 and xz compressed files",
+        opt: Opt::Decompress,
+    },
+    Spec {
+        short: None,
+        long: "watch",
+        value: None,
+        help: "Keep watching the paths, searching lines as they are added",
+        opt: Opt::Watch,
+    },
+    Spec {
+        short: None,
+        long: "profile",
+        value: Some("NAME"),
+        help: "Apply the options of profile NAME from the config files",
+        opt: Opt::Profile,
+    },
+    Spec {
+        short: None,
+        long: "no-config",
+        value: None,
+        help: "Ignore the config files",
+        opt: Opt::NoConfig,
+    },
+    Spec {
+        short: None,
+        long: "debug-config",
+        value: None,
+        help: "Print the options in effect and where each was set",
+        opt: Opt::DebugConfig,
+    },
+    Spec {
+        short: Some('j'),
+        long: "threads",
+        value: Some("NUM"),
+        help: "Search NUM files at once (default: one per CPU)",
+        opt: Opt::Jobs,
+    },
+    Spec {
+        short: Some('h'),
+        long: "help",
+        value: None,
+        help: "Print this help and exit",
+        opt: Opt::Help,
+    },
+    Spec {
+        short: Some('V'),
+        long: "version",
+        value: None,
+        help: "Print the version and exit",
+        opt: Opt::Version,
+    },
+];
+
+/// Parses the command line, including the program name in the first
+/// position. Short flags may be combined (`-in`) and may carry their value
+/// directly (`-A2`); long options take their value either as `--opt=value`
+/// or as the next argument. Everything after `--` is positional.
+///
+/// The first positional argument is the query and the rest are files or
+/// directories to search, unless patterns were given with `-e` or `-f`, in
+/// which case every positional argument is a path. Without any paths,
+/// standard input is searched.
+///
+/// A command line starting with `index build` or `index search` works with
+/// the trigram index instead, taking the same options. To search for the
+/// word "index", use `-e index` or put it after `--`.
+///
+/// Configuration files are not consulted; see `parse_args_with`.
+pub fn parse_args<I>(args: I) -> Result<Command, ArgsError>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+{
+    parse_args_with(args, &Settings::default())
+}
+
+/// Like `parse_args`, applying the defaults and profiles of configuration
+/// files, which the command line overrides. `--no-config` ignores them.
+pub fn parse_args_with<I>(args: I, settings: &Settings) -> Result<Command, ArgsError>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+{
+    let mut args = args.into_iter();
+    args.next();
+    let args = args
+        .map(|arg| arg.into().into_string().map_err(ArgsError::NotUnicode))
+        .collect::<Result<Vec<String>, ArgsError>>()?;
+    let mut args = args.into_iter().peekable();
+
+    if args.peek().map(String::as_str) != Some("index") {
+        return parse_search(args, true, settings);
+    }
+    args.next();
+    let command = args.next().unwrap_or_default();
+    let (takes_query, wrap): (bool, fn(Config) -> Command) = match command.as_str() {
+        "build" => (false, Command::IndexBuild),
+        "search" => (true, Command::IndexSearch),
+        _ => return Err(ArgsError::UnknownCommand(command)),
+    };
+    match parse_search(args, takes_query, settings)? {
+        Command::Search(config) => Ok(wrap(config)),
+        other => Ok(other),
+    }
+}
+
+/// Parses the options and positional arguments of a search. Without
+/// `takes_query`, every positional argument is a path.
+fn parse_search<I>(
+    mut args: I,
+    takes_query: bool,
+    settings: &Settings,
+) -> Result<Command, ArgsError>
+where
+    I: Iterator<Item = String>,
+{
+    let mut given = Vec::new();
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref());
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let spec = match SPECS.iter().find(|spec| spec.long == name) {
+                Some(v) => v,
+                None => return Err(ArgsError::UnknownOption(format!("--{}", name))),
+            };
+            let option = format!("--{}", name);
+            let value = match (spec.value, inline) {
+                (Some(_), Some(value)) => Some(value),
+                (Some(_), None) => {
+                    Some(args.next().ok_or(ArgsError::MissingValue(option.clone()))?)
+                }
+                (None, Some(_)) => return Err(ArgsError::UnexpectedValue(option)),
+                (None, None) => None,
+            };
+            if let Some(command) = immediate(spec.opt) {
+                return Ok(command);
+            }
+            given.push(Given::command_line(spec.opt, option, value));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            // a group of short flags, where a flag taking a value consumes
+            // the rest of the group or else the next argument
+            for (i, c) in arg[1..].char_indices() {
+                let option = format!("-{}", c);
+                let spec = match SPECS.iter().find(|spec| spec.short == Some(c)) {
+                    Some(v) => v,
+                    None => return Err(ArgsError::UnknownOption(option)),
+                };
+                let value = match spec.value {
+                    Some(_) => {
+                        let rest = &arg[1 + i + c.len_utf8()..];
+                        let value = if rest.is_empty() {
+                            args.next().ok_or(ArgsError::MissingValue(option.clone()))?
+                        } else {
+                            rest.to_string()
+                        };
+                        Some(value)
+                    }
+                    None => None,
+                };
+                if let Some(command) = immediate(spec.opt) {
+                    return Ok(command);
+                }
+                let takes_value = value.is_some();
+                given.push(Given::command_line(spec.opt, option, value));
+                if takes_value {
+                    break;
+                }
+            }
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let no_config = given.iter().any(|g| g.opt == Opt::NoConfig);
+    let debug = given.iter().any(|g| g.opt == Opt::DebugConfig);
+    let mut config = Config {
+        case_sensitive: true,
+        ..Config::default()
+    };
+    let mut applied: Vec<Given> = Vec::new();
+    for g in layers(given, settings, no_config)? {
+        apply(&mut config, g.opt, &g.option, g.value.clone())?;
+        resolve(&mut applied, g);
+    }
+
+    // patterns given with -e or -f leave every positional argument a path
+    let mut positional = positional.into_iter();
+    if takes_query && config.patterns.is_empty() && config.pattern_files.is_empty() {
+        match positional.next() {
+            Some(query) => config.query = query,
+            None if debug => {}
+            None => return Err(ArgsError::MissingQuery),
+        }
+    }
+    config.paths = positional.collect();
+    if debug {
+        let files = match no_config {
+            true => None,
+            false => Some(settings.files.as_slice()),
+        };
+        return Ok(Command::DebugConfig(debug_report(files, &applied)));
+    }
+
+    if config.replace.is_none() {
+        let option = if config.dry_run {
+            Some("--dry-run")
+        } else if config.in_place {
+            Some("--in-place")
+        } else {
+            None
+        };
+        if let Some(option) = option {
+            return Err(ArgsError::Requires {
+                option: option.to_string(),
+                required: "--replace".to_string(),
+            });
+        }
+    }
+    if config.fuzzy.is_some() && config.regex {
+        return Err(ArgsError::Conflicts {
+            option: "--fuzzy".to_string(),
+            other: "--regex".to_string(),
+        });
+    }
+    Ok(Command::Search(config))
+}
+
+/// An option to apply to the config, and where it was given.
+struct Given {
+    opt: Opt,
+    /// How the option is named in error messages.
+    option: String,
+    value: Option<String>,
+    source: Source,
+}
+
+impl Given {
+    fn command_line(opt: Opt, option: String, value: Option<String>) -> Given {
+        Given {
+            opt,
+            option,
+            value,
+            source: Source::CommandLine,
+        }
+    }
+}
+
+/// The command for options that replace the search entirely, as `--help`
+/// and `--version` do.
+fn immediate(opt: Opt) -> Option<Command> {
+    match opt {
+        Opt::Help => Some(Command::Help),
+        Opt::Version => Some(Command::Version),
+        _ => None,
+    }
+}
+
+/// Options that add to their earlier values rather than replacing them.
+fn repeatable(opt: Opt) -> bool {
+    matches!(
+        opt,
+        Opt::Exclude | Opt::Include | Opt::Pattern | Opt::PatternFile
+    )
+}
+
+/// Options that configuration files may set: defaults for how results are
+/// presented and which files are searched. Those changing what a search
+/// does or how it exits, such as `--count`, `--quiet` or `--invert-match`,
+/// would surprise a script run on another machine, so only make sense on
+/// the command line.
+fn configurable(opt: Opt) -> bool {
+    matches!(
+        opt,
+        Opt::AfterContext
+            | Opt::BeforeContext
+            | Opt::ByteOffset
+            | Opt::Color
+            | Opt::Column
+            | Opt::Context
+            | Opt::Exclude
+            | Opt::Hidden
+            | Opt::IgnoreCase
+            | Opt::Include
+            | Opt::LineNumber
+            | Opt::NoIgnore
+            | Opt::SmartCase
+    )
+}
+
+/// Puts the options from every source in the order they are applied, each
+/// overriding the ones before: the defaults from the configuration files,
+/// the profile chosen with `--profile`, the `CASE_INSENSITIVE` environment
+/// variable and finally the command line.
+fn layers(
+    command_line: Vec<Given>,
+    settings: &Settings,
+    no_config: bool,
+) -> Result<Vec<Given>, ArgsError> {
+    let mut given = Vec::new();
+    if !no_config {
+        if let Some(e) = settings.error() {
+            return Err(ArgsError::Config(e.to_string()));
+        }
+        for setting in settings.defaults() {
+            add_setting(&mut given, setting)?;
+        }
+    }
+    let profile = command_line
+        .iter()
+        .rev()
+        .find(|g| g.opt == Opt::Profile)
+        .and_then(|g| g.value.clone());
+    if let Some(name) = profile {
+        let settings = match no_config {
+            true => None,
+            false => settings.profile(&name),
+        };
+        for setting in settings.ok_or(ArgsError::UnknownProfile(name))? {
+            add_setting(&mut given, setting)?;
+        }
+    }
+    if env::var("CASE_INSENSITIVE").is_ok() {
+        given.push(Given {
+            opt: Opt::IgnoreCase,
+            option: "CASE_INSENSITIVE".to_string(),
+            value: None,
+            source: Source::Environment("CASE_INSENSITIVE"),
+        });
+    }
+    given.extend(command_line);
+    Ok(given)
+}
+
+/// Adds the options a configuration file setting stands for. Setting a flag
+/// to `false` drops it where an earlier file set it.
+fn add_setting(given: &mut Vec<Given>, setting: &Setting) -> Result<(), ArgsError> {
+    let invalid = |reason: &str| {
+        ArgsError::Config(format!("{}: '{}' {}", setting.source, setting.key, reason))
+    };
+    let spec = SPECS
+        .iter()
+        .find(|spec| spec.long == setting.key)
+        .ok_or_else(|| invalid("is not an option"))?;
+    if !configurable(spec.opt) {
+        return Err(invalid("can't be set in a configuration file"));
+    }
+    let option = format!("{} in {}", setting.key, setting.source);
+    let from_file = |value| Given {
+        opt: spec.opt,
+        option: option.clone(),
+        value,
+        source: setting.source.clone(),
+    };
+    match (&setting.value, spec.value) {
+        (SettingValue::Flag(true), None) => given.push(from_file(None)),
+        (SettingValue::Flag(false), None) => given.retain(|g| g.opt != spec.opt),
+        (SettingValue::Text(value), Some(_)) => given.push(from_file(Some(value.clone()))),
+        (SettingValue::List(values), Some(_)) if repeatable(spec.opt) => {
+            given.extend(values.iter().map(|v| from_file(Some(v.clone()))))
+        }
+        (_, None) => return Err(invalid("is a flag, set to true or false")),
+        (SettingValue::List(_), Some(_)) => return Err(invalid("takes a single value")),
+        (SettingValue::Flag(_), Some(_)) => return Err(invalid("takes a value")),
+    }
+    Ok(())
+}
+
+/// Adds an option to those in effect, dropping or narrowing the earlier ones
+/// it overrides, so that `--debug-config` reports the resolved values.
+fn resolve(applied: &mut Vec<Given>, g: Given) {
+    let modes = |opt| {
+        matches!(
+            opt,
+            Opt::Count | Opt::FilesWithMatches | Opt::FilesWithoutMatch | Opt::Quiet
+        )
+    };
+    if !repeatable(g.opt) {
+        applied.retain(|a| a.opt != g.opt);
+    }
+    match g.opt {
+        Opt::IgnoreCase => applied.retain(|a| a.opt != Opt::SmartCase),
+        Opt::SmartCase => applied.retain(|a| a.opt != Opt::IgnoreCase),
+        Opt::Context => {
+            applied.retain(|a| !matches!(a.opt, Opt::AfterContext | Opt::BeforeContext))
+        }
+        Opt::AfterContext | Opt::BeforeContext => {
+            // an earlier --context is left setting the other side only
+            if let Some(context) = applied.iter_mut().find(|a| a.opt == Opt::Context) {
+                context.opt = match g.opt {
+                    Opt::AfterContext => Opt::BeforeContext,
+                    _ => Opt::AfterContext,
+                };
+            }
+        }
+        // quiet mode can't be undone
+        opt if modes(opt) && applied.iter().any(|a| a.opt == Opt::Quiet) => return,
+        opt if modes(opt) => applied.retain(|a| !modes(a.opt)),
+        _ => {}
+    }
+    applied.push(g);
+}
+
+/// Text printed by `--debug-config`: the configuration files read, unless
+/// they are ignored, and the resolved value of each option with where it
+/// was set.
+fn debug_report(files: Option<&[PathBuf]>, applied: &[Given]) -> String {
+    let mut text = String::from("Configuration files:\n");
+    match files {
+        None => text.push_str("  ignored with --no-config\n"),
+        Some([]) => text.push_str("  none found\n"),
+        Some(files) => {
+            for path in files {
+                text.push_str(&format!("  {}\n", path.display()));
+            }
+        }
+    }
+
+    text.push_str("\nOptions:\n");
+    let options: Vec<(String, &Source)> = applied
+        .iter()
+        .filter(|g| g.opt != Opt::DebugConfig)
+        .map(|g| {
+            let long = SPECS.iter().find(|spec| spec.opt == g.opt).unwrap().long;
+            let value = match &g.value {
+                None => "true".to_string(),
+                Some(v) if v.parse::<usize>().is_ok() => v.clone(),
+                Some(v) => format!("{:?}", v),
+            };
+            (format!("{} = {}", long, value), &g.source)
+        })
+        .collect();
+    if options.is_empty() {
+        text.push_str("  none\n");
+    }
+    let width = options.iter().map(|(option, _)| option.len()).max();
+    for (option, source) in &options {
+        text.push_str(&format!(
+            "  {:<width$}  {}\n",
+            option,
+            source,
+            width = width.unwrap_or(0)
+        ));
+    }
+    text
+}
+
+/// Applies a single option to the config.
+fn apply(
+    config: &mut Config,
+    opt: Opt,
+    option: &str,
+    value: Option<String>,
+) -> Result<(), ArgsError> {
+    let value = value.unwrap_or_default();
+    match opt {
+        Opt::AfterContext => config.after_context = parse_number(option, &value)?,
+        Opt::BeforeContext => config.before_context = parse_number(option, &value)?,
+        Opt::Context => {
+            config.after_context = parse_number(option, &value)?;
+            config.before_context = config.after_context;
+        }
+        Opt::ByteOffset => config.byte_offset = true,
+        Opt::Count => set_mode(config, OutputMode::Count),
+        Opt::Decompress => config.decompress = true,
+        Opt::DryRun => {
+            config.in_place = true;
+            config.dry_run = true;
+        }
+        Opt::FilesWithMatches => set_mode(config, OutputMode::FilesWithMatches),
+        Opt::FilesWithoutMatch => set_mode(config, OutputMode::FilesWithoutMatch),
+        Opt::Color => {
+            config.color = value.parse().map_err(|_| ArgsError::InvalidValue {
+                option: option.to_string(),
+                value: value.clone(),
+            })?
+        }
+        Opt::Column => config.column = true,
+        Opt::Exclude => config.exclude.push(value),
+        Opt::Fuzzy => config.fuzzy = Some(parse_number(option, &value)?),
+        Opt::Hidden => config.hidden = true,
+        Opt::IgnoreCase => {
+            config.case_sensitive = false;
+            config.smart_case = false;
+        }
+        Opt::InPlace => config.in_place = true,
+        Opt::Include => config.include.push(value),
+        Opt::Invert => config.invert = true,
+        Opt::Jobs => config.jobs = parse_number(option, &value)?,
+        Opt::Json => config.json = true,
+        Opt::LineNumber => config.line_number = true,
+        Opt::NoIgnore => config.no_ignore = true,
+        Opt::Pattern => config.patterns.push(value),
+        Opt::PatternFile => config.pattern_files.push(value),
+        Opt::Quiet => config.mode = OutputMode::Quiet,
+        Opt::Regex => config.regex = true,
+        Opt::Replace => config.replace = Some(value),
+        Opt::SkipBinary => config.skip_binary = true,
+        Opt::SmartCase => config.smart_case = true,
+        Opt::Watch => config.watch = true,
+        Opt::Word => config.word = true,
+        // handled while gathering the options rather than setting any
+        Opt::DebugConfig | Opt::Help | Opt::NoConfig | Opt::Profile | Opt::Version => {}
+    }
+    Ok(())
+}
+
+/// The last output mode given wins, except that quiet mode can't be undone.
+fn set_mode(config: &mut Config, mode: OutputMode) {
+    if config.mode != OutputMode::Quiet {
+        config.mode = mode;
+    }
+}
+
+fn parse_number(option: &str, value: &str) -> Result<usize, ArgsError> {
+    value.parse().map_err(|_| ArgsError::InvalidValue {
+        option: option.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Text printed by `--help`, generated from the option table.
+pub fn help() -> String {
+    let mut text = format!(
+        "minigrep {}\n\nUsage: minigrep [OPTIONS] QUERY [PATH]...\n       \
+         minigrep [OPTIONS] -e PATTERN... [PATH]...\n       \
+         minigrep index build [OPTIONS] [DIR]\n       \
+         minigrep index search [OPTIONS] QUERY [DIR]\n\n\
+         Searches each PATH, or standard input when no PATH or `-` is given.\n\
+         Directories are searched recursively. Default options and profiles\n\
+         are read from ~/.config/minigrep/config.toml and .minigrep.toml.\n\n\
+         Options:\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    for spec in SPECS {
+        let short = match spec.short {
+            Some(c) => format!("-{}, ", c),
+            None => "    ".to_string(),
+        };
+        let long = match spec.value {
+            Some(value) => format!("--{} {}", spec.long, value),
+            None => format!("--{}", spec.long),
+        };
+        text.push_str(&format!("  {}{:<24} {}\n", short, long, spec.help));
+    }
+    text
+}
+
+/// Text printed by `--version`.
+pub fn version() -> String {
+    format!("minigrep {}\n", env!("CARGO_PKG_VERSION"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ColorChoice;
+    use std::path::Path;
+
+    fn parse(args: &[&str]) -> Result<Command, ArgsError> {
+        parse_args(std::iter::once("minigrep").chain(args.iter().copied()))
+    }
+
+    fn search(args: &[&str]) -> Config {
+        match parse(args) {
+            Ok(Command::Search(config)) => config,
+            other => panic!("expected a search, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn positional_arguments() {
+        let config = search(&["duct", "poem.txt", "src"]);
+        assert_eq!("duct", config.query);
+        assert_eq!(vec!["poem.txt", "src"], config.paths);
+
+        assert!(search(&["duct"]).paths.is_empty());
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let config = search(&["-inE", "duct"]);
+        assert!(!config.case_sensitive);
+        assert!(config.line_number);
+        assert!(config.regex);
+    }
+
+    #[test]
+    fn short_values() {
+        let config = search(&["-znA2", "-B", "3", "-j4", "duct"]);
+        assert!(config.decompress);
+        assert!(config.line_number);
+        assert_eq!(2, config.after_context);
+        assert_eq!(3, config.before_context);
+        assert_eq!(4, config.jobs);
+    }
+
+    #[test]
+    fn long_values() {
+        let config = search(&[
+            "--context=2",
+            "--include",
+            "*.rs",
+            "--exclude=target",
+            "duct",
+        ]);
+        assert_eq!(2, config.after_context);
+        assert_eq!(2, config.before_context);
+        assert_eq!(vec!["*.rs"], config.include);
+        assert_eq!(vec!["target"], config.exclude);
+    }
+
+    #[test]
+    fn output_modes() {
+        assert_eq!(OutputMode::Lines, search(&["duct"]).mode);
+        assert_eq!(OutputMode::Count, search(&["-c", "duct"]).mode);
+        assert_eq!(OutputMode::FilesWithoutMatch, search(&["-lL", "duct"]).mode);
+        assert_eq!(OutputMode::Quiet, search(&["-qc", "duct"]).mode);
+        assert!(search(&["-v", "duct"]).invert);
+    }
+
+    #[test]
+    fn color() {
+        assert_eq!(ColorChoice::Auto, search(&["duct"]).color);
+        assert_eq!(
+            ColorChoice::Always,
+            search(&["--color=always", "duct"]).color
+        );
+        assert_eq!(
+            ColorChoice::Never,
+            search(&["--color", "never", "duct"]).color
+        );
+        assert_eq!(
+            ArgsError::InvalidValue {
+                option: "--color".to_string(),
+                value: "rainbow".to_string()
+            },
+            parse(&["--color=rainbow", "duct"]).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn patterns() {
+        let config = search(&["-e", "unwrap", "-eexpect", "-f", "banned.txt", "src"]);
+        assert_eq!(vec!["unwrap", "expect"], config.patterns);
+        assert_eq!(vec!["banned.txt"], config.pattern_files);
+        assert_eq!("", config.query);
+        assert_eq!(vec!["src"], config.paths);
+
+        assert!(search(&["--file=banned.txt"]).paths.is_empty());
+    }
+
+    #[test]
+    fn index_commands() {
+        match parse(&["index", "build", "--hidden", "src"]) {
+            Ok(Command::IndexBuild(config)) => {
+                assert!(config.hidden);
+                assert_eq!(vec!["src"], config.paths);
+            }
+            other => panic!("expected an index build, got {:?}", other),
+        }
+        match parse(&["index", "search", "-n", "duct", "src"]) {
+            Ok(Command::IndexSearch(config)) => {
+                assert!(config.line_number);
+                assert_eq!("duct", config.query);
+                assert_eq!(vec!["src"], config.paths);
+            }
+            other => panic!("expected an index search, got {:?}", other),
+        }
+        assert!(matches!(
+            parse(&["index", "build", "-h"]),
+            Ok(Command::Help)
+        ));
+        assert_eq!(
+            ArgsError::UnknownCommand("drop".to_string()),
+            parse(&["index", "drop"]).unwrap_err()
+        );
+        assert_eq!("index", search(&["--", "index"]).query);
+        assert_eq!(vec!["index"], search(&["-e", "index"]).patterns);
+    }
+
+    #[test]
+    fn case_and_words() {
+        let config = search(&["-Sw", "--watch", "duct"]);
+        assert!(config.watch);
+        assert!(config.smart_case);
+        assert!(config.word);
+
+        // the last of -i and --smart-case wins
+        assert!(!search(&["-S", "-i", "duct"]).smart_case);
+        assert!(search(&["-i", "--smart-case", "duct"]).smart_case);
+    }
+
+    #[test]
+    fn replace() {
+        let config = search(&["-r", "$1", "--in-place", "(duct)", "src"]);
+        assert_eq!(Some("$1".to_string()), config.replace);
+        assert!(config.in_place);
+        assert!(!config.dry_run);
+
+        let config = search(&["--replace=x", "--dry-run", "duct"]);
+        assert!(config.in_place);
+        assert!(config.dry_run);
+
+        assert_eq!(
+            ArgsError::Requires {
+                option: "--dry-run".to_string(),
+                required: "--replace".to_string()
+            },
+            parse(&["--dry-run", "duct"]).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn fuzzy() {
+        assert_eq!(Some(2), search(&["--fuzzy=2", "duct"]).fuzzy);
+        assert_eq!(Some(0), search(&["--fuzzy", "0", "duct"]).fuzzy);
+        assert_eq!(None, search(&["duct"]).fuzzy);
+        assert_eq!(
+            ArgsError::Conflicts {
+                option: "--fuzzy".to_string(),
+                other: "--regex".to_string()
+            },
+            parse(&["-E", "--fuzzy=1", "duct"]).unwrap_err()
+        );
+    }
+
+    fn settings(files: &[(&str, &str)]) -> Settings {
+        let mut settings = Settings::default();
+        for (path, text) in files {
+            settings.parse(Path::new(path), text);
+        }
+        settings
+    }
+
+    fn parse_with(args: &[&str], settings: &Settings) -> Result<Command, ArgsError> {
+        let args = std::iter::once("minigrep").chain(args.iter().copied());
+        parse_args_with(args.map(String::from), settings)
+    }
+
+    fn search_with(args: &[&str], settings: &Settings) -> Config {
+        match parse_with(args, settings) {
+            Ok(Command::Search(config)) => config,
+            other => panic!("expected a search, got {:?}", other),
+        }
+    }
+
+    const USER: &str = "\
+context = 2
+smart-case = true
+exclude = [\"target\"]
+
+[profile.rust]
+include = [\"*.rs\"]
+column = true
+";
+
+    #[test]
+    fn config_defaults_and_overrides() {
+        let settings = settings(&[("user.toml", USER)]);
+        let config = search_with(&["duct"], &settings);
+        assert_eq!(2, config.before_context);
+        assert!(config.smart_case);
+        assert_eq!(vec!["target"], config.exclude);
+        assert!(config.include.is_empty());
+
+        // the command line replaces single values and adds to lists
+        let config = search_with(&["-A1", "-i", "--exclude=*.md", "duct"], &settings);
+        assert_eq!((2, 1), (config.before_context, config.after_context));
+        assert!(!config.smart_case);
+        assert_eq!(vec!["target", "*.md"], config.exclude);
+
+        let config = search_with(&["--no-config", "duct"], &settings);
+        assert_eq!(0, config.before_context);
+        assert!(config.exclude.is_empty());
+    }
+
+    #[test]
+    fn config_profiles() {
+        let project = "smart-case = false\n[profile.rust]\ncolumn = false\n";
+        let settings = settings(&[("user.toml", USER), (".minigrep.toml", project)]);
+        let config = search_with(&["duct"], &settings);
+        assert!(!config.smart_case);
+
+        let config = search_with(&["--profile", "rust", "duct"], &settings);
+        assert_eq!(vec!["*.rs"], config.include);
+        assert!(!config.column);
+        assert_eq!(
+            ArgsError::UnknownProfile("python".to_string()),
+            parse_with(&["--profile=python", "duct"], &settings).unwrap_err()
+        );
+        assert_eq!(
+            ArgsError::UnknownProfile("rust".to_string()),
+            parse_with(&["--profile=rust", "--no-config", "duct"], &settings).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn config_errors() {
+        let error = |text: &str, args: &[&str]| {
+            parse_with(args, &settings(&[("a.toml", text)])).unwrap_err()
+        };
+        let config_error = |text: &str| match error(text, &["duct"]) {
+            ArgsError::Config(message) => message,
+            other => panic!("expected a config error, got {:?}", other),
+        };
+        assert_eq!("a.toml: 'nope' is not an option", config_error("nope = 1"));
+        assert_eq!(
+            "a.toml [profile.x]: 'replace' can't be set in a configuration file",
+            error("[profile.x]\nreplace = \"y\"", &["--profile=x", "duct"]).to_string()
+        );
+        assert_eq!(
+            "a.toml: 'hidden' is a flag, set to true or false",
+            config_error("hidden = \"yes\"")
+        );
+        for option in ["count", "quiet", "invert-match", "json", "watch"] {
+            assert_eq!(
+                format!("a.toml: '{}' can't be set in a configuration file", option),
+                config_error(&format!("{} = true", option))
+            );
+        }
+        assert_eq!(
+            "a.toml: 'threads' can't be set in a configuration file",
+            config_error("threads = \"4\"")
+        );
+        assert_eq!(
+            "a.toml: 'color' takes a single value",
+            config_error("color = [\"always\"]")
+        );
+        assert_eq!(
+            ArgsError::InvalidValue {
+                option: "context in a.toml".to_string(),
+                value: "lots".to_string()
+            },
+            error("context = \"lots\"", &["duct"])
+        );
+
+        // an unreadable file can be worked around
+        assert!(config_error("context = ").starts_with("a.toml: "));
+        assert!(parse_with(
+            &["--no-config", "duct"],
+            &settings(&[("a.toml", "context = ")])
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn debug_config() {
+        let settings = settings(&[("user.toml", USER)]);
+        let args = ["--profile", "rust", "--debug-config", "-C1", "-i", "-A2"];
+        let report = match parse_with(&args, &settings) {
+            Ok(Command::DebugConfig(report)) => report,
+            other => panic!("expected a report, got {:?}", other),
+        };
+        // -i overrides smart-case, and -A2 leaves -C1 the before-context
+        assert_eq!(
+            "\
+Configuration files:
+  user.toml
+
+Options:
+  exclude = \"target\"  user.toml
+  include = \"*.rs\"    user.toml [profile.rust]
+  column = true       user.toml [profile.rust]
+  profile = \"rust\"    command line
+  before-context = 1  command line
+  ignore-case = true  command line
+  after-context = 2   command line
+",
+            report
+        );
+
+        let report = match parse_with(&["-q", "-c", "--debug-config", "-l"], &settings) {
+            Ok(Command::DebugConfig(report)) => report,
+            other => panic!("expected a report, got {:?}", other),
+        };
+        assert!(report.contains("  quiet = true "));
+        assert!(!report.contains("count") && !report.contains("files-with-matches"));
+
+        let report = match parse_with(&["--no-config", "--debug-config"], &settings) {
+            Ok(Command::DebugConfig(report)) => report,
+            other => panic!("expected a report, got {:?}", other),
+        };
+        assert_eq!(
+            "Configuration files:\n  ignored with --no-config\n\nOptions:\n  no-config = true  command line\n",
+            report
+        );
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let config = search(&["-n", "--", "-v", "--json"]);
+        assert!(config.line_number);
+        assert!(!config.json);
+        assert_eq!("-v", config.query);
+        assert_eq!(vec!["--json"], config.paths);
+    }
+
+    #[test]
+    fn lone_dash_is_positional() {
+        assert_eq!(vec!["-"], search(&["duct", "-"]).paths);
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert!(matches!(parse(&["-h"]), Ok(Command::Help)));
+        assert!(matches!(parse(&["duct", "--help"]), Ok(Command::Help)));
+        assert!(matches!(parse(&["-V"]), Ok(Command::Version)));
+        assert!(help().contains("--after-context NUM"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(ArgsError::MissingQuery, parse(&["-n"]).unwrap_err());
+        assert_eq!(
+            ArgsError::UnknownOption("-x".to_string()),
+            parse(&["-nx", "duct"]).unwrap_err()
+        );
+        assert_eq!(
+            ArgsError::UnknownOption("--nope".to_string()),
+            parse(&["--nope", "duct"]).unwrap_err()
+        );
+        assert_eq!(
+            ArgsError::MissingValue("-A".to_string()),
+            parse(&["duct", "-A"]).unwrap_err()
+        );
+        assert_eq!(
+            ArgsError::UnexpectedValue("--json".to_string()),
+            parse(&["--json=yes", "duct"]).unwrap_err()
+        );
+        assert_eq!(
+            ArgsError::InvalidValue {
+                option: "--context".to_string(),
+                value: "lots".to_string()
+            },
+            parse(&["--context=lots", "duct"]).unwrap_err()
+        );
+        assert_eq!(EXIT_ERROR, ArgsError::MissingQuery.exit_code());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_unicode_arguments_are_rejected() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let bad = OsString::from_vec(b"du\xffct".to_vec());
+        let err = parse_args(vec![OsString::from("minigrep"), bad.clone()]).unwrap_err();
+        assert_eq!(ArgsError::NotUnicode(bad), err);
+        assert_eq!(
+            "Argument 'du\u{fffd}ct' is not valid UTF-8",
+            err.to_string()
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+
+/// When to highlight output, as given to `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorChoice {
+    /// Highlight only when standard output is a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolves the choice against the current standard output.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ColorChoice, ()> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(()),
+        }
+    }
+}
+
+/// SGR parameters (the `01;31` in `ESC[01;31m`) used for each part of a line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colors {
+    pub matched: String,
+    pub path: String,
+    pub line_number: String,
+    pub column: String,
+    pub byte_offset: String,
+    pub separator: String,
+}
+
+impl Default for Colors {
+    /// The same defaults as GNU grep.
+    fn default() -> Colors {
+        Colors {
+            matched: "01;31".to_string(),
+            path: "35".to_string(),
+            line_number: "32".to_string(),
+            column: "32".to_string(),
+            byte_offset: "32".to_string(),
+            separator: "36".to_string(),
+        }
+    }
+}
+
+impl Colors {
+    /// Reads colours from a `GREP_COLORS` style specification such as
+    /// `mt=01;32:fn=34:se=0`, starting from the defaults. The keys are `mt`
+    /// (match), `fn` (file name), `ln` (line number), `cn` (column), `bn`
+    /// (byte offset) and `se` (separator). Unknown keys and values that are
+    /// not SGR parameters are ignored.
+    pub fn from_spec(spec: &str) -> Colors {
+        let mut colors = Colors::default();
+        for entry in spec.split(':') {
+            let (key, value) = match entry.split_once('=') {
+                Some(v) => v,
+                None => continue,
+            };
+            if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                continue;
+            }
+            let field = match key {
+                "mt" => &mut colors.matched,
+                "fn" => &mut colors.path,
+                "ln" => &mut colors.line_number,
+                "cn" => &mut colors.column,
+                "bn" => &mut colors.byte_offset,
+                "se" => &mut colors.separator,
+                _ => continue,
+            };
+            *field = value.to_string();
+        }
+        colors
+    }
+}
+
+/// Wraps the text in the escape sequences for the given SGR parameters.
+pub fn paint(sgr: &str, text: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", sgr, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_choice() {
+        assert_eq!(Ok(ColorChoice::Always), "always".parse());
+        assert_eq!(Ok(ColorChoice::Never), "never".parse());
+        assert_eq!(Err(()), "sometimes".parse::<ColorChoice>());
+        assert!(ColorChoice::Always.enabled());
+        assert!(!ColorChoice::Never.enabled());
+    }
+
+    #[test]
+    fn spec_overrides_defaults() {
+        let colors = Colors::from_spec("mt=01;32:fn=34:xx=1:ln=bold:se");
+        assert_eq!("01;32", colors.matched);
+        assert_eq!("34", colors.path);
+        assert_eq!("32", colors.line_number);
+        assert_eq!("36", colors.separator);
+    }
+
+    #[test]
+    fn paint_wraps_text() {
+        assert_eq!("\x1b[35mpoem.txt\x1b[0m", paint("35", "poem.txt"));
+    }
+}
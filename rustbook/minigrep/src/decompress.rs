@@ -0,0 +1,165 @@
+use crate::READ_BUFFER;
+use std::io::{self, BufRead, BufReader};
+
+/// Compression formats recognised by `-z`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Identifies the format from the magic bytes at the start of a stream.
+    pub fn detect(header: &[u8]) -> Option<Compression> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if header.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else {
+            None
+        }
+    }
+
+    /// Wraps the reader in a streaming decoder. Concatenated members, as
+    /// produced by appending to a compressed log, are all decoded.
+    pub fn decoder<'a, R: BufRead + 'a>(self, reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+        let decoder: Box<dyn io::Read + 'a> = match self {
+            Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+            Compression::Bzip2 => Box::new(bzip2::bufread::MultiBzDecoder::new(reader)),
+            Compression::Xz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader)),
+        };
+        Ok(Box::new(BufReader::with_capacity(READ_BUFFER, decoder)))
+    }
+}
+
+/// Returns a reader over the decompressed contents when the input starts
+/// with a known magic number, or the input itself otherwise.
+pub fn maybe_decompress<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    match Compression::detect(reader.fill_buf()?) {
+        Some(compression) => compression.decoder(reader),
+        None => Ok(Box::new(reader)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{search_files, Config, Matcher, PrintOptions, Printer};
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+
+    const LOG: &str = "GET /index.html 200\nGET /missing 404\nPOST /login 200\n";
+
+    fn compress(compression: Compression, data: &[u8]) -> Vec<u8> {
+        match compression {
+            Compression::Gzip => {
+                let mut e = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+            Compression::Zstd => zstd::encode_all(data, 0).unwrap(),
+            Compression::Bzip2 => {
+                let mut e = bzip2::write::BzEncoder::new(Vec::new(), Default::default());
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+            Compression::Xz => {
+                let mut e = xz2::write::XzEncoder::new(Vec::new(), 6);
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+        }
+    }
+
+    const ALL: [Compression; 4] = [
+        Compression::Gzip,
+        Compression::Zstd,
+        Compression::Bzip2,
+        Compression::Xz,
+    ];
+
+    #[test]
+    fn round_trip() {
+        for compression in ALL {
+            let compressed = compress(compression, LOG.as_bytes());
+            assert_eq!(Some(compression), Compression::detect(&compressed));
+
+            let mut out = String::new();
+            maybe_decompress(&compressed[..])
+                .unwrap()
+                .read_to_string(&mut out)
+                .unwrap();
+            assert_eq!(LOG, out, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn concatenated_members() {
+        for compression in ALL {
+            let mut compressed = compress(compression, b"first\n");
+            compressed.extend(compress(compression, b"second\n"));
+            let mut out = String::new();
+            maybe_decompress(&compressed[..])
+                .unwrap()
+                .read_to_string(&mut out)
+                .unwrap();
+            assert_eq!("first\nsecond\n", out, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn plain_input_is_untouched() {
+        assert_eq!(None, Compression::detect(LOG.as_bytes()));
+        let mut out = String::new();
+        maybe_decompress(LOG.as_bytes())
+            .unwrap()
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(LOG, out);
+    }
+
+    #[test]
+    fn search_keeps_original_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths: Vec<PathBuf> = ["access.log.gz", "access.log.1.zst"]
+            .iter()
+            .zip([Compression::Gzip, Compression::Zstd])
+            .map(|(name, compression)| {
+                let path = dir.path().join(name);
+                fs::write(&path, compress(compression, LOG.as_bytes())).unwrap();
+                path
+            })
+            .collect();
+        let plain = dir.path().join("access.log");
+        fs::write(&plain, LOG).unwrap();
+        paths.push(plain);
+
+        let config = Config {
+            decompress: true,
+            jobs: 1,
+            ..Config::default()
+        };
+        let options = PrintOptions {
+            with_path: true,
+            ..PrintOptions::default()
+        };
+        let mut printer = Printer::new(Vec::new(), options);
+        let matcher = Matcher::literal("404", true);
+        search_files(&paths, &matcher, &config, &mut printer).unwrap();
+
+        let out = String::from_utf8(printer.into_inner()).unwrap();
+        let expected: String = paths
+            .iter()
+            .map(|path| format!("{}:GET /missing 404\n", path.display()))
+            .collect();
+        assert_eq!(expected, out);
+    }
+}
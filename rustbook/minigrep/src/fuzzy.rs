@@ -0,0 +1,335 @@
+use crate::matcher::fold;
+use std::error::Error;
+use std::ops::Range;
+
+/// Longest pattern the bit vectors can hold, in characters.
+const MAX_LEN: usize = 64;
+
+/// Approximate substring search: finds the parts of a line within a
+/// Levenshtein distance of the pattern, counting insertions, deletions and
+/// substitutions of characters.
+///
+/// Uses Myers' bit-parallel algorithm, which keeps a column of the dynamic
+/// programming matrix in two bit vectors and advances it a whole column per
+/// character of the line, so a line is searched in linear time whatever the
+/// number of edits allowed.
+#[derive(Debug, Clone)]
+pub struct Fuzzy {
+    /// Length of the pattern in characters, after folding.
+    len: usize,
+    max: usize,
+    case_sensitive: bool,
+    forward: Peq,
+    /// The table for the reversed pattern, used to find where a match
+    /// starts once its end is known.
+    backward: Peq,
+}
+
+impl Fuzzy {
+    /// Constructs a search for the pattern allowing up to `max` edits. The
+    /// pattern must be longer than `max`, or else every line would match,
+    /// and at most 64 characters long.
+    pub fn new(pattern: &str, max: usize, case_sensitive: bool) -> Result<Fuzzy, Box<dyn Error>> {
+        let chars: Vec<char> = match case_sensitive {
+            true => pattern.chars().collect(),
+            false => pattern.chars().flat_map(fold).collect(),
+        };
+        if chars.len() <= max {
+            return Err(format!(
+                "pattern '{}' must be longer than the {} edits allowed by --fuzzy",
+                pattern, max
+            )
+            .into());
+        }
+        if chars.len() > MAX_LEN {
+            return Err(format!(
+                "pattern '{}' is too long for --fuzzy, which allows at most {} characters",
+                pattern, MAX_LEN
+            )
+            .into());
+        }
+        Ok(Fuzzy {
+            len: chars.len(),
+            max,
+            case_sensitive,
+            forward: Peq::new(chars.iter().copied()),
+            backward: Peq::new(chars.iter().rev().copied()),
+        })
+    }
+
+    /// Returns true when some part of the line is within the distance.
+    pub fn is_match(&self, line: &str) -> bool {
+        let mut search = Search::new(self.len, false);
+        let mut found = false;
+        self.each_char(line, |c| {
+            found = search.step(self.forward.get(c)) <= self.max;
+            found
+        });
+        found
+    }
+
+    /// Returns the first match at or after `start`, which must be a
+    /// character boundary, and its distance. Of overlapping candidates the
+    /// closest is taken, and extended both ways as far as it stays as close:
+    /// `konfig` rather than `onfig` for `config`.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<(Range<usize>, usize)> {
+        // advance until the distance drops within the limit, then carry on
+        // while it doesn't rise to reach the best end of that match
+        let mut search = Search::new(self.len, false);
+        let mut best: Option<(usize, usize)> = None;
+        for (i, c) in line[start..].char_indices() {
+            let mut score = usize::MAX;
+            self.each_folded(c, |f| {
+                score = score.min(search.step(self.forward.get(f)));
+                false
+            });
+            match best {
+                Some((_, distance)) if score <= distance => best = Some((i, score)),
+                Some(_) => break,
+                None if score <= self.max => best = Some((i, score)),
+                None => {}
+            }
+        }
+        let (at, distance) = best?;
+        let end = start + at + line[start + at..].chars().next().map_or(0, char::len_utf8);
+
+        // search back from the end with the reversed pattern anchored
+        // there, taking the earliest start before the distance rises again
+        let mut search = Search::new(self.len, true);
+        let mut from = end;
+        'back: for (i, c) in line[start..end].char_indices().rev() {
+            let mut folded = Vec::with_capacity(1);
+            self.each_folded(c, |f| {
+                folded.push(f);
+                false
+            });
+            for f in folded.into_iter().rev() {
+                match search.step(self.backward.get(f)) {
+                    score if score == distance => from = start + i,
+                    score if score > distance && from < end => break 'back,
+                    _ => {}
+                }
+            }
+        }
+        Some((from..end, distance))
+    }
+
+    /// Edit distance between the pattern and the whole of `text`.
+    pub fn distance(&self, text: &str) -> usize {
+        let mut search = Search::new(self.len, true);
+        let mut score = self.len;
+        self.each_char(text, |c| {
+            score = search.step(self.forward.get(c));
+            false
+        });
+        score
+    }
+
+    /// Feeds each character of the text to `f`, folded when searching case
+    /// insensitively, stopping early when it returns true.
+    fn each_char(&self, text: &str, mut f: impl FnMut(char) -> bool) {
+        for c in text.chars() {
+            if self.each_folded(c, &mut f) {
+                return;
+            }
+        }
+    }
+
+    fn each_folded(&self, c: char, mut f: impl FnMut(char) -> bool) -> bool {
+        match self.case_sensitive {
+            true => f(c),
+            false => fold(c).any(f),
+        }
+    }
+}
+
+/// The pattern's match vectors: bit `i` of a character's vector is set when
+/// the pattern has that character at position `i`.
+#[derive(Debug, Clone)]
+struct Peq {
+    ascii: [u64; 128],
+    other: Vec<(char, u64)>,
+}
+
+impl Peq {
+    fn new(chars: impl Iterator<Item = char>) -> Peq {
+        let mut peq = Peq {
+            ascii: [0; 128],
+            other: Vec::new(),
+        };
+        for (i, c) in chars.enumerate() {
+            let bit = 1 << i;
+            if c.is_ascii() {
+                peq.ascii[c as usize] |= bit;
+            } else {
+                match peq.other.iter_mut().find(|(other, _)| *other == c) {
+                    Some((_, bits)) => *bits |= bit,
+                    None => peq.other.push((c, bit)),
+                }
+            }
+        }
+        peq
+    }
+
+    fn get(&self, c: char) -> u64 {
+        if c.is_ascii() {
+            return self.ascii[c as usize];
+        }
+        self.other
+            .iter()
+            .find(|(other, _)| *other == c)
+            .map_or(0, |&(_, bits)| bits)
+    }
+}
+
+/// One column of the distance matrix, held as vertical deltas: `pv` has a
+/// bit set where a cell is one more than the one above it and `mv` where it
+/// is one less.
+struct Search {
+    pv: u64,
+    mv: u64,
+    score: usize,
+    last: u64,
+    /// Whether the match must start where the text does. Otherwise it may
+    /// start anywhere, the top row of the matrix being all zeroes.
+    anchored: bool,
+}
+
+impl Search {
+    fn new(len: usize, anchored: bool) -> Search {
+        Search {
+            pv: !0,
+            mv: 0,
+            score: len,
+            last: 1 << (len - 1),
+            anchored,
+        }
+    }
+
+    /// Advances past a character with the given match vector, returning the
+    /// distance between the pattern and the best text ending there.
+    fn step(&mut self, eq: u64) -> usize {
+        let xv = eq | self.mv;
+        let xh = ((eq & self.pv).wrapping_add(self.pv) ^ self.pv) | eq;
+        let mut ph = self.mv | !(xh | self.pv);
+        let mut mh = self.pv & xh;
+        if ph & self.last != 0 {
+            self.score += 1;
+        } else if mh & self.last != 0 {
+            self.score -= 1;
+        }
+        ph = (ph << 1) | self.anchored as u64;
+        mh <<= 1;
+        self.pv = mh | !(xv | ph);
+        self.mv = ph & xv;
+        self.score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Textbook dynamic programming edit distance to check against.
+    fn levenshtein(a: &str, b: &str) -> usize {
+        let b: Vec<char> = b.chars().collect();
+        let mut row: Vec<usize> = (0..=b.len()).collect();
+        for (i, ca) in a.chars().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, &cb) in b.iter().enumerate() {
+                let next = (diagonal + (ca != cb) as usize)
+                    .min(row[j] + 1)
+                    .min(row[j + 1] + 1);
+                diagonal = row[j + 1];
+                row[j + 1] = next;
+            }
+        }
+        row[b.len()]
+    }
+
+    fn find<'a>(pattern: &str, max: usize, line: &'a str) -> Option<(&'a str, usize)> {
+        let fuzzy = Fuzzy::new(pattern, max, true).unwrap();
+        fuzzy.find_at(line, 0).map(|(m, d)| (&line[m], d))
+    }
+
+    #[test]
+    fn exact_and_approximate_matches() {
+        assert_eq!(Some(("error", 0)), find("error", 1, "an error here"));
+        assert_eq!(Some(("eror", 1)), find("error", 1, "an eror here"));
+        assert_eq!(Some(("errr", 1)), find("error", 2, "an errro here"));
+        assert_eq!(Some(("eorr", 2)), find("error", 2, "an eorr here"));
+        assert_eq!(Some(("eror", 1)), find("error", 2, "erorr"));
+        assert_eq!(None, find("error", 1, "an eorr here"));
+        assert_eq!(None, find("error", 1, "nothing wrong"));
+    }
+
+    #[test]
+    fn matches_extend_while_as_close() {
+        // "abc" is already one edit away, as are "abcx" and "abcxd"
+        assert_eq!(Some(("abcxd", 1)), find("abcd", 1, "abcxde"));
+        assert_eq!(Some(("receive", 0)), find("receive", 2, "to receive it"));
+    }
+
+    #[test]
+    fn later_matches_from_start() {
+        let fuzzy = Fuzzy::new("fast", 1, true).unwrap();
+        let line = "fest and fats";
+        assert_eq!(Some((0..4, 1)), fuzzy.find_at(line, 0));
+        assert_eq!(Some((9..12, 1)), fuzzy.find_at(line, 4));
+    }
+
+    #[test]
+    fn distances_agree_with_dynamic_programming() {
+        let fuzzy = Fuzzy::new("kitten", 3, true).unwrap();
+        for text in ["kitten", "sitting", "kitchen", "", "mitten on", "nettik"] {
+            assert_eq!(
+                levenshtein("kitten", text),
+                fuzzy.distance(text),
+                "{}",
+                text
+            );
+        }
+        // each match is as far as reported, and the closest one is as
+        // close as any part of the line
+        for line in ["the sitting cat", "smitten", "kit ten", "kitchen sink"] {
+            let best = (0..=line.len())
+                .flat_map(|i| (i..=line.len()).map(move |j| &line[i..j]))
+                .map(|s| levenshtein("kitten", s))
+                .min()
+                .unwrap();
+            let mut closest = None;
+            let mut at = 0;
+            while let Some((m, d)) = fuzzy.find_at(line, at) {
+                assert_eq!(levenshtein("kitten", &line[m.clone()]), d, "{}", line);
+                closest = closest.min(Some(d)).or(Some(d));
+                at = m.end;
+            }
+            assert_eq!(Some(best), closest, "{}", line);
+        }
+    }
+
+    #[test]
+    fn case_folding() {
+        let fuzzy = Fuzzy::new("Straße", 1, false).unwrap();
+        assert!(fuzzy.is_match("STRASSE"));
+        assert!(fuzzy.is_match("the strase"));
+        assert_eq!(Some((4..11, 0)), fuzzy.find_at("the strasse", 0));
+        assert!(!Fuzzy::new("Straße", 1, true).unwrap().is_match("STRASSE"));
+    }
+
+    #[test]
+    fn non_ascii_characters() {
+        let fuzzy = Fuzzy::new("naïve", 1, true).unwrap();
+        assert_eq!(Some((4..9, 1)), fuzzy.find_at("too naive", 0));
+        assert_eq!(Some((4..10, 0)), fuzzy.find_at("too naïve", 0));
+    }
+
+    #[test]
+    fn rejects_unusable_patterns() {
+        assert!(Fuzzy::new("ab", 2, true).is_err());
+        assert!(Fuzzy::new("", 0, true).is_err());
+        assert!(Fuzzy::new(&"a".repeat(65), 1, true).is_err());
+        assert!(Fuzzy::new(&"a".repeat(64), 1, true).is_ok());
+    }
+}
@@ -0,0 +1,565 @@
+use crate::matcher::fold;
+use crate::{
+    build_matcher, collect_files, is_binary, patterns, print_options, search_files, write_atomic,
+    Config, Outcome, OutputMode, Printer,
+};
+use regex_syntax::hir::literal::Extractor;
+use regex_syntax::ParserBuilder;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryInto;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Name of the index file kept at the root of an indexed tree.
+pub const INDEX_FILE: &str = ".minigrep-index";
+
+/// Identifies the file format, including its version.
+const MAGIC: &[u8] = b"MGIDX\x02";
+
+/// Three consecutive bytes packed into the low 24 bits.
+type Trigram = u32;
+
+/// An indexed file and the metadata used to tell whether it has changed.
+#[derive(Debug, Clone, PartialEq)]
+struct FileEntry {
+    /// Path relative to the root of the index.
+    path: PathBuf,
+    /// Modification time as seconds and nanoseconds since the epoch.
+    modified: (u64, u32),
+    len: u64,
+    /// The file is binary, so none of its trigrams are recorded.
+    binary: bool,
+}
+
+impl FileEntry {
+    fn new(path: PathBuf, metadata: &fs::Metadata) -> FileEntry {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or((0, 0), |d| (d.as_secs(), d.subsec_nanos()));
+        FileEntry {
+            path,
+            modified,
+            len: metadata.len(),
+            binary: false,
+        }
+    }
+
+    /// Whether both entries describe the same version of the same file.
+    fn same_version(&self, other: &FileEntry) -> bool {
+        (&self.path, self.modified, self.len) == (&other.path, other.modified, other.len)
+    }
+
+    /// True when the file on disk no longer matches what was indexed,
+    /// including when it can't be read.
+    fn is_stale(&self, root: &Path) -> bool {
+        match fs::metadata(root.join(&self.path)) {
+            Ok(metadata) => !FileEntry::new(self.path.clone(), &metadata).same_version(self),
+            Err(_) => true,
+        }
+    }
+}
+
+/// What an index build did.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct IndexStats {
+    /// Files in the index.
+    pub files: usize,
+    /// Files that were new or changed and had to be read.
+    pub read: usize,
+    /// Files dropped because they were deleted or are now excluded.
+    pub removed: usize,
+    /// At least one file could not be read.
+    pub errors: bool,
+}
+
+/// Maps every trigram of the case folded text to the files containing it.
+/// A search only needs to read the files holding all the trigrams of its
+/// query. Trigrams spanning a line break are left out, since a match never
+/// does.
+#[derive(Debug, Default, PartialEq)]
+pub struct Index {
+    files: Vec<FileEntry>,
+    /// Sorted file ids for each trigram.
+    postings: BTreeMap<Trigram, Vec<u32>>,
+}
+
+impl Index {
+    /// Indexes the files under `root`. Files whose modification time and
+    /// size match the previous index keep their trigrams without being read
+    /// again. Binary files are recorded without their trigrams.
+    fn build(root: &Path, files: &[PathBuf], previous: Option<&Index>) -> (Index, IndexStats) {
+        let previous_ids: HashMap<&Path, usize> = previous
+            .map(|index| {
+                index
+                    .files
+                    .iter()
+                    .enumerate()
+                    .map(|(id, entry)| (entry.path.as_path(), id))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut stats = IndexStats::default();
+        let mut entries = Vec::new();
+        let mut reused = HashMap::new();
+        let mut trigrams_of = Vec::new();
+        for path in files {
+            let relative = path.strip_prefix(root).unwrap_or(path).to_path_buf();
+            let metadata = match fs::metadata(path) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    stats.errors = true;
+                    continue;
+                }
+            };
+            let mut entry = FileEntry::new(relative, &metadata);
+            let unchanged = previous_ids
+                .get(entry.path.as_path())
+                .copied()
+                .filter(|&id| previous.unwrap().files[id].same_version(&entry));
+            match unchanged {
+                Some(old) => {
+                    entry.binary = previous.unwrap().files[old].binary;
+                    reused.insert(old as u32, entries.len());
+                    trigrams_of.push(Vec::new());
+                }
+                None => {
+                    let bytes = match fs::read(path) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            eprintln!("{}: {}", path.display(), e);
+                            stats.errors = true;
+                            continue;
+                        }
+                    };
+                    stats.read += 1;
+                    entry.binary = is_binary(&bytes);
+                    trigrams_of.push(match entry.binary {
+                        true => Vec::new(),
+                        false => trigrams(&String::from_utf8_lossy(&bytes)),
+                    });
+                }
+            }
+            entries.push(entry);
+        }
+
+        // recover the trigrams of unchanged files from the old postings
+        if let Some(previous) = previous {
+            for (&trigram, ids) in &previous.postings {
+                for id in ids {
+                    if let Some(&new) = reused.get(id) {
+                        trigrams_of[new].push(trigram);
+                    }
+                }
+            }
+            let kept: HashSet<&Path> = entries.iter().map(|e| e.path.as_path()).collect();
+            stats.removed = previous
+                .files
+                .iter()
+                .filter(|entry| !kept.contains(entry.path.as_path()))
+                .count();
+        }
+
+        let mut postings: BTreeMap<Trigram, Vec<u32>> = BTreeMap::new();
+        for (id, trigrams) in trigrams_of.iter().enumerate() {
+            for &trigram in trigrams {
+                postings.entry(trigram).or_default().push(id as u32);
+            }
+        }
+        stats.files = entries.len();
+        let index = Index {
+            files: entries,
+            postings,
+        };
+        (index, stats)
+    }
+
+    /// Returns the files that may contain a match. `required` lists
+    /// alternatives, each a set of trigrams that must all be present; `None`
+    /// means the query can't be narrowed. Binary files, and files changed
+    /// since the index was built, are always included.
+    fn candidates(&self, root: &Path, required: Option<&[Vec<Trigram>]>) -> Vec<PathBuf> {
+        let mut selected = vec![required.is_none(); self.files.len()];
+        for trigrams in required.unwrap_or_default() {
+            for id in self.intersect(trigrams) {
+                selected[id as usize] = true;
+            }
+        }
+        self.files
+            .iter()
+            .zip(selected)
+            .filter(|(entry, selected)| *selected || entry.binary || entry.is_stale(root))
+            .map(|(entry, _)| root.join(&entry.path))
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    /// The files holding every one of the trigrams.
+    fn intersect(&self, trigrams: &[Trigram]) -> Vec<u32> {
+        let mut lists: Vec<&[u32]> = Vec::with_capacity(trigrams.len());
+        for trigram in trigrams {
+            match self.postings.get(trigram) {
+                Some(ids) => lists.push(ids),
+                None => return Vec::new(),
+            }
+        }
+        lists.sort_by_key(|ids| ids.len());
+        let (first, rest) = match lists.split_first() {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+        first
+            .iter()
+            .copied()
+            .filter(|id| rest.iter().all(|ids| ids.binary_search(id).is_ok()))
+            .collect()
+    }
+
+    /// Reads an index written by `save`.
+    pub fn load(path: &Path) -> io::Result<Index> {
+        let bytes = fs::read(path)?;
+        let mut r = Decoder {
+            bytes: bytes
+                .strip_prefix(MAGIC)
+                .ok_or_else(|| invalid("not a minigrep index"))?,
+        };
+        let mut files = Vec::new();
+        for _ in 0..r.u32()? {
+            let modified = (r.u64()?, r.u32()?);
+            let len = r.u64()?;
+            let binary = r.take(1)?[0] != 0;
+            let n = r.u32()? as usize;
+            let path = String::from_utf8(r.take(n)?.to_vec()).map_err(|_| invalid("bad path"))?;
+            files.push(FileEntry {
+                path: PathBuf::from(path),
+                modified,
+                len,
+                binary,
+            });
+        }
+        let mut postings = BTreeMap::new();
+        for _ in 0..r.u32()? {
+            let trigram = r.u32()?;
+            let ids = (0..r.u32()?).map(|_| r.u32()).collect::<io::Result<_>>()?;
+            postings.insert(trigram, ids);
+        }
+        Ok(Index { files, postings })
+    }
+
+    /// Writes the index atomically with `write_atomic`. Files with paths that
+    /// aren't valid UTF-8 are skipped.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = MAGIC.to_vec();
+        let files: Vec<(usize, &FileEntry, &str)> = self
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(id, entry)| entry.path.to_str().map(|p| (id, entry, p)))
+            .collect();
+        let mut ids = vec![None; self.files.len()];
+        out.extend(&(files.len() as u32).to_le_bytes());
+        for (new, (old, entry, path)) in files.iter().enumerate() {
+            ids[*old] = Some(new as u32);
+            out.extend(&entry.modified.0.to_le_bytes());
+            out.extend(&entry.modified.1.to_le_bytes());
+            out.extend(&entry.len.to_le_bytes());
+            out.push(entry.binary as u8);
+            out.extend(&(path.len() as u32).to_le_bytes());
+            out.extend(path.as_bytes());
+        }
+        out.extend(&(self.postings.len() as u32).to_le_bytes());
+        for (trigram, posting) in &self.postings {
+            let posting: Vec<u32> = posting.iter().filter_map(|&id| ids[id as usize]).collect();
+            out.extend(&trigram.to_le_bytes());
+            out.extend(&(posting.len() as u32).to_le_bytes());
+            for id in posting {
+                out.extend(&id.to_le_bytes());
+            }
+        }
+        write_atomic(path, &out)
+    }
+}
+
+/// Reads little-endian integers from the front of a byte slice.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(invalid("truncated index"));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The sorted, distinct trigrams of the case folded text.
+fn trigrams(text: &str) -> Vec<Trigram> {
+    let folded: String = text.chars().flat_map(fold).collect();
+    let mut trigrams: Vec<Trigram> = folded
+        .as_bytes()
+        .windows(3)
+        .filter(|w| !w.contains(&b'\n'))
+        .map(|w| (w[0] as u32) << 16 | (w[1] as u32) << 8 | w[2] as u32)
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+/// Works out which trigrams a line must contain to match any of the
+/// patterns, as alternatives of trigram sets. Regular expressions contribute
+/// the literal prefixes every match must start with. Returns `None` when some
+/// pattern can't be narrowed, such as one shorter than three bytes.
+fn required_trigrams(patterns: &[String], regex: bool) -> Option<Vec<Vec<Trigram>>> {
+    let mut alternatives = Vec::new();
+    for pattern in patterns {
+        if !regex {
+            alternatives.push(Some(trigrams(pattern)).filter(|t| !t.is_empty())?);
+            continue;
+        }
+        let hir = ParserBuilder::new().build().parse(pattern).ok()?;
+        for literal in Extractor::new().extract(&hir).literals()? {
+            // a prefix cut inside a character still has a valid prefix
+            let bytes = literal.as_bytes();
+            let text = match std::str::from_utf8(bytes) {
+                Ok(v) => v,
+                Err(e) => std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap(),
+            };
+            alternatives.push(Some(trigrams(text)).filter(|t| !t.is_empty())?);
+        }
+    }
+    Some(alternatives)
+}
+
+/// `minigrep index build`: indexes the directory, or the current directory,
+/// updating any index already there.
+pub fn build_index(config: &Config) -> Result<IndexStats, Box<dyn Error>> {
+    let root = index_root(config)?;
+    let index_path = root.join(INDEX_FILE);
+    let previous = match Index::load(&index_path) {
+        Ok(v) => Some(v),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            eprintln!("{}: {}, rebuilding", index_path.display(), e);
+            None
+        }
+    };
+
+    let (files, walk_errors) = collect_files(&[root], config)?;
+    let files: Vec<PathBuf> = files.into_iter().filter(|f| *f != index_path).collect();
+    let (index, mut stats) = Index::build(root, &files, previous.as_ref());
+    index.save(&index_path)?;
+    stats.errors |= walk_errors;
+    Ok(stats)
+}
+
+/// `minigrep index search`: searches the files of an index that may match,
+/// verifying them with the normal search. Files added since the index was
+/// built are not searched until it is rebuilt.
+pub fn search_index(config: &Config) -> Result<Outcome, Box<dyn Error>> {
+    let root = index_root(config)?;
+    let index_path = root.join(INDEX_FILE);
+    let index = Index::load(&index_path).map_err(|e| {
+        format!(
+            "{}: {} (run 'minigrep index build {}' first)",
+            index_path.display(),
+            e,
+            root.display()
+        )
+    })?;
+
+    let patterns = patterns(config)?;
+    let matcher = build_matcher(config, &patterns)?;
+    // every file is needed when reporting the ones that don't match, and
+    // approximate matches needn't contain any of the query's trigrams
+    let narrow = !config.invert
+        && config.fuzzy.is_none()
+        && !matches!(
+            config.mode,
+            OutputMode::Count | OutputMode::FilesWithoutMatch
+        );
+    let required = match narrow {
+        true => required_trigrams(&patterns, config.regex),
+        false => None,
+    };
+    let files = index.candidates(root, required.as_deref());
+
+    let stdout = io::stdout();
+    let options = print_options(config, patterns, true);
+    let mut printer = Printer::new(stdout.lock(), options);
+    let outcome = search_files(&files, &matcher, config, &mut printer)?;
+    printer.finish()?;
+    Ok(outcome)
+}
+
+fn index_root(config: &Config) -> Result<&Path, Box<dyn Error>> {
+    match config.paths.as_slice() {
+        [] => Ok(Path::new(".")),
+        [dir] if Path::new(dir).is_dir() => Ok(Path::new(dir)),
+        [dir] => Err(format!("{}: not a directory", dir).into()),
+        _ => Err("an index covers a single directory".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_tree(dir: &Path) -> Vec<PathBuf> {
+        let files = [
+            ("main.rs", "fn main() {\n    let x = parse().unwrap();\n}\n"),
+            ("lib.rs", "pub fn parse() -> Option<u32> {\n    None\n}\n"),
+            ("README", "Straße\nUnwrap carefully\n"),
+        ];
+        files
+            .iter()
+            .map(|(name, contents)| {
+                let path = dir.join(name);
+                fs::write(&path, contents).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    fn names(paths: Vec<PathBuf>) -> Vec<String> {
+        paths
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn find(index: &Index, root: &Path, patterns: &[&str], regex: bool) -> Vec<String> {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        let required = required_trigrams(&patterns, regex);
+        names(index.candidates(root, required.as_deref()))
+    }
+
+    #[test]
+    fn narrows_candidates() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let files = write_tree(root);
+        let (index, stats) = Index::build(root, &files, None);
+        assert_eq!(3, stats.files);
+        assert_eq!(3, stats.read);
+
+        assert_eq!(
+            vec!["main.rs", "README"],
+            find(&index, root, &["unwrap"], false)
+        );
+        assert_eq!(vec!["README"], find(&index, root, &["STRASSE"], false));
+        assert_eq!(
+            vec!["lib.rs"],
+            find(&index, root, &["None", "Option"], false)
+        );
+        assert!(find(&index, root, &["missing"], false).is_empty());
+
+        // regex prefixes narrow the search, anything else doesn't
+        assert_eq!(
+            vec!["main.rs", "lib.rs"],
+            find(&index, root, &[r"(?:pub )?fn \w+"], true)
+        );
+        assert_eq!(vec!["lib.rs"], find(&index, root, &[r"Option<\w+>"], true));
+        assert_eq!(3, find(&index, root, &[r"\w+\(\)"], true).len());
+        assert_eq!(3, find(&index, root, &["fn"], false).len());
+    }
+
+    #[test]
+    fn round_trips_through_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let files = write_tree(root);
+        let (index, _) = Index::build(root, &files, None);
+        let path = root.join(INDEX_FILE);
+        index.save(&path).unwrap();
+        assert_eq!(index, Index::load(&path).unwrap());
+
+        fs::write(&path, b"nonsense").unwrap();
+        assert!(Index::load(&path).is_err());
+    }
+
+    #[test]
+    fn updates_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let mut files = write_tree(root);
+        let (index, _) = Index::build(root, &files, None);
+
+        // a change of size is noticed even within the mtime resolution
+        fs::write(&files[1], "pub fn parse() -> Result<u32, ()> {\n}\n").unwrap();
+        fs::remove_file(&files[2]).unwrap();
+        files.pop();
+        let added = root.join("new.rs");
+        fs::write(&added, "// Option\n").unwrap();
+        files.push(added);
+
+        // before the rebuild, the stale file is still searched
+        assert_eq!(vec!["lib.rs"], find(&index, root, &["Result"], false));
+
+        let (updated, stats) = Index::build(root, &files, Some(&index));
+        assert_eq!(
+            IndexStats {
+                files: 3,
+                read: 2,
+                removed: 1,
+                errors: false
+            },
+            stats
+        );
+        assert_eq!(vec!["lib.rs"], find(&updated, root, &["Result"], false));
+        assert_eq!(vec!["new.rs"], find(&updated, root, &["Option"], false));
+        assert_eq!(vec!["main.rs"], find(&updated, root, &["unwrap()"], false));
+
+        let (fresh, _) = Index::build(root, &files, None);
+        assert_eq!(fresh, updated);
+    }
+
+    #[test]
+    fn records_binary_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let mut files = write_tree(root);
+        let blob = root.join("blob.bin");
+        fs::write(&blob, b"unwrap\0Option").unwrap();
+        files.push(blob);
+
+        let (index, stats) = Index::build(root, &files, None);
+        assert_eq!((4, 4), (stats.files, stats.read));
+        let path = root.join(INDEX_FILE);
+        index.save(&path).unwrap();
+        let index = Index::load(&path).unwrap();
+        assert!(index.files[3].binary);
+
+        // binary files have no trigrams, so they are always searched
+        assert_eq!(
+            vec!["lib.rs", "blob.bin"],
+            find(&index, root, &["Option"], false)
+        );
+        assert_eq!(vec!["blob.bin"], find(&index, root, &["missing"], false));
+
+        let (_, stats) = Index::build(root, &files, Some(&index));
+        assert_eq!((4, 0), (stats.files, stats.read));
+    }
+}
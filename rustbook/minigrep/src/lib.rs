@@ -1,70 +1,509 @@
+//! A small grep. Besides the `minigrep` binary, the library can be used as a
+//! search engine by other crates:
+//!
+//! ```
+//! use minigrep::{search_with, Matcher};
+//!
+//! let matcher = Matcher::regex(r"fast|three", true).unwrap();
+//! let matches = search_with(&matcher, "Rust:\nsafe, fast, productive.\nPick three.");
+//!
+//! assert_eq!(2, matches[0].line_number);
+//! assert_eq!(6, matches[0].byte_offset());
+//! assert_eq!(Some(7), matches[0].column());
+//! assert_eq!(vec![6..10], matches[0].submatches);
+//! ```
+
+use std::env;
 use std::error::Error;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+mod cli;
+mod color;
+mod decompress;
+mod fuzzy;
+mod index;
+mod literal;
+mod matcher;
+mod parallel;
+mod printer;
+mod replace;
+mod settings;
+mod stream;
+mod walk;
+#[cfg(target_os = "linux")]
+mod watch;
+
+pub use cli::{help, parse_args, parse_args_with, version, ArgsError, Command};
+pub use color::{ColorChoice, Colors};
+pub use decompress::{maybe_decompress, Compression};
+pub use fuzzy::Fuzzy;
+pub use index::{build_index, search_index, Index, IndexStats, INDEX_FILE};
+pub use literal::Finder;
+pub use matcher::{has_uppercase, Matcher};
+pub use parallel::search_files;
+pub use printer::{OutputMode, PrintOptions, Printer};
+pub use replace::{apply_edits, line_edits, rewrite_files, unified_diff, write_atomic, LineEdit};
+pub use settings::{Setting, SettingValue, Settings, Source, PROJECT_FILE};
+pub use stream::{reader_matches, search_reader, search_reader_at, Position, Stats};
+pub use walk::{walk, walk_dirs, GlobFilter, WalkOptions};
+#[cfg(target_os = "linux")]
+pub use watch::{timestamp, watch, Tail};
+
+/// Number of leading bytes inspected when deciding whether a file is binary.
+const BINARY_BLOCK: usize = 8 * 1024;
+
+/// Size of the read buffer used when streaming a file.
+const READ_BUFFER: usize = 64 * 1024;
+
+/// Name given to standard input in place of a file name.
+const STDIN_NAME: &str = "-";
+
+/// Exit status when at least one line was selected.
+pub const EXIT_MATCH: i32 = 0;
+/// Exit status when no line was selected.
+pub const EXIT_NO_MATCH: i32 = 1;
+/// Exit status when an error occurred.
+pub const EXIT_ERROR: i32 = 2;
 
+/// Everything needed to run a search, usually built by `parse_args`.
+#[derive(Debug, Default)]
 pub struct Config {
+    /// The pattern to search for, unless `patterns` or `pattern_files` are
+    /// given.
     pub query: String,
-    pub filename: String,
+    /// Patterns given with `-e`. A line is selected when any pattern matches.
+    pub patterns: Vec<String>,
+    /// Files holding one pattern per line, given with `-f`.
+    pub pattern_files: Vec<String>,
+    /// Files and directories to search. Empty means standard input.
+    pub paths: Vec<String>,
     pub case_sensitive: bool,
+    /// Decide case sensitivity from the query: sensitive only when it
+    /// contains an uppercase letter. Overrides `case_sensitive`.
+    pub smart_case: bool,
+    pub regex: bool,
+    /// Only match whole words.
+    pub word: bool,
+    /// Match text within this many edits of the query rather than the query
+    /// itself, ranking the lines by how close they are.
+    pub fuzzy: Option<usize>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub hidden: bool,
+    pub no_ignore: bool,
+    pub skip_binary: bool,
+    /// Search inside gzip, zstd, bzip2 and xz files, recognised by their
+    /// magic bytes rather than their names.
+    pub decompress: bool,
+    /// Keep watching the paths and search what changes.
+    pub watch: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub column: bool,
+    pub json: bool,
+    /// Number of files searched concurrently, 0 meaning one per CPU.
+    pub jobs: usize,
+    /// Select the lines that don't match instead of those that do.
+    pub invert: bool,
+    pub mode: OutputMode,
+    pub color: ColorChoice,
+    /// Template substituted for each match, `$1` or `${name}` naming a
+    /// capture group when searching with a regex.
+    pub replace: Option<String>,
+    /// Rewrite the files with the replacements instead of printing lines.
+    pub in_place: bool,
+    /// With `in_place`, print a unified diff instead of writing the files.
+    pub dry_run: bool,
 }
 
-impl Config {
-    pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
-        args.next();
+/// Summary of a whole run, used to pick the exit status.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Outcome {
+    /// At least one line was selected.
+    pub matched: bool,
+    /// At least one file could not be searched.
+    pub errors: bool,
+}
 
-        let query = match args.next() {
-            Some(v) => v,
-            None => return Err("Didn't get a query string"),
-        };
+impl Outcome {
+    /// Exit status following grep: 0 when a line was selected, 1 when none
+    /// were and 2 when an error occurred. In quiet mode a match takes
+    /// precedence over errors.
+    pub fn exit_code(&self, mode: OutputMode) -> i32 {
+        match (self.matched, self.errors) {
+            (true, _) if mode == OutputMode::Quiet => EXIT_MATCH,
+            (_, true) => EXIT_ERROR,
+            (true, false) => EXIT_MATCH,
+            (false, false) => EXIT_NO_MATCH,
+        }
+    }
+}
 
-        let filename = match args.next() {
-            Some(v) => v,
-            None => return Err("Didn't get a file name"),
-        };
-        let case_sensitive = std::env::var("CASE_INSENSITIVE").is_err();
+pub fn run(config: Config) -> Result<Outcome, Box<dyn Error>> {
+    let patterns = patterns(&config)?;
+    let matcher = build_matcher(&config, &patterns)?;
 
-        Ok(Config {
-            query,
-            filename,
-            case_sensitive,
-        })
+    if config.watch {
+        return watch(&config, &matcher, patterns);
+    }
+
+    let paths: Vec<&Path> = match config.paths.is_empty() {
+        true => vec![Path::new(STDIN_NAME)],
+        false => config.paths.iter().map(Path::new).collect(),
+    };
+
+    if let (true, Some(template)) = (config.in_place, &config.replace) {
+        if paths.contains(&Path::new(STDIN_NAME)) {
+            return Err("standard input can't be rewritten in place".into());
+        }
+        let (files, walk_errors) = collect_files(&paths, &config)?;
+        let stdout = io::stdout();
+        let mut outcome = rewrite_files(&files, &matcher, template, config.dry_run, stdout.lock())?;
+        outcome.errors |= walk_errors;
+        return Ok(outcome);
+    }
+
+    let stdout = io::stdout();
+    let with_path = paths.len() > 1 || paths.iter().any(|path| path.is_dir());
+    let options = print_options(&config, patterns, with_path);
+    let mut printer = Printer::new(stdout.lock(), options);
+
+    // a lone file is searched directly so that failing to read it is an
+    // error for the whole run
+    if paths.len() == 1 && !paths[0].is_dir() {
+        let stats = search_file(paths[0], &matcher, &config, &mut printer)?;
+        printer.finish()?;
+        return Ok(Outcome {
+            matched: stats.matched_lines > 0,
+            errors: false,
+        });
+    }
+
+    // When searching several files or a directory, a file that can't be read
+    // is reported and skipped rather than ending the whole search. Each
+    // result is prefixed with the path of the file it came from.
+    let (files, walk_errors) = collect_files(&paths, &config)?;
+    let mut outcome = search_files(&files, &matcher, &config, &mut printer)?;
+    outcome.errors |= walk_errors;
+    printer.finish()?;
+
+    Ok(outcome)
+}
+
+/// Expands directories into the files beneath them that pass the include,
+/// exclude and ignore rules. Directories that can't be walked, in whole or
+/// in part, are reported and flagged in the returned bool.
+pub(crate) fn collect_files(
+    paths: &[&Path],
+    config: &Config,
+) -> Result<(Vec<PathBuf>, bool), Box<dyn Error>> {
+    let filter = GlobFilter::new(&config.include, &config.exclude)?;
+    let options = WalkOptions {
+        hidden: config.hidden,
+        ignore: !config.no_ignore,
+    };
+    let mut files = Vec::new();
+    let mut walk_errors = false;
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.to_path_buf());
+            continue;
+        }
+        match walk(path, &filter, options) {
+            Ok((v, errors)) => {
+                files.extend(v);
+                walk_errors |= errors;
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                walk_errors = true;
+            }
+        }
     }
+    Ok((files, walk_errors))
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.filename)?;
+/// Decides case sensitivity, which `--smart-case` takes from the patterns.
+pub(crate) fn case_sensitive(config: &Config, patterns: &[String]) -> bool {
+    match config.smart_case {
+        true => patterns.iter().any(|p| has_uppercase(p, config.regex)),
+        false => config.case_sensitive,
+    }
+}
 
-    let results = if config.case_sensitive {
-        search(&config.query, &contents)
+/// Builds the matcher for the patterns with the config's search options.
+pub(crate) fn build_matcher(
+    config: &Config,
+    patterns: &[String],
+) -> Result<Matcher, Box<dyn Error>> {
+    let case_sensitive = case_sensitive(config, patterns);
+    let mut matcher = if let Some(max) = config.fuzzy {
+        match patterns {
+            [pattern] => Matcher::fuzzy(pattern, max, case_sensitive)?,
+            _ => return Err("--fuzzy searches for a single pattern".into()),
+        }
+    } else if config.regex {
+        Matcher::regexes(patterns, case_sensitive)?
     } else {
-        search_case_insensitive(&config.query, &contents)
+        Matcher::literals(patterns, case_sensitive)?
     };
+    if config.word {
+        matcher = matcher.whole_word();
+    }
+    Ok(matcher)
+}
 
-    for line in results {
-        println!("{}", line);
+/// Translates the config's output options for the printer. The patterns are
+/// only reported when there are several of them. Approximate matches are
+/// ranked unless context or JSON output ties them to their place in the
+/// file.
+pub(crate) fn print_options(
+    config: &Config,
+    patterns: Vec<String>,
+    with_path: bool,
+) -> PrintOptions {
+    PrintOptions {
+        mode: config.mode,
+        before: config.before_context,
+        after: config.after_context,
+        with_path,
+        line_number: config.line_number,
+        byte_offset: config.byte_offset,
+        column: config.column,
+        json: config.json,
+        colors: colors(config.color),
+        replaced: config.replace.is_some(),
+        patterns: match patterns.len() {
+            1 => Vec::new(),
+            _ => patterns,
+        },
+        rank: config.fuzzy.is_some()
+            && !config.invert
+            && !config.json
+            && config.before_context == 0
+            && config.after_context == 0,
     }
+}
 
-    Ok(())
+/// The patterns given with `-e` and read from `-f` files, one per line with
+/// blank lines skipped, or else the query.
+pub(crate) fn patterns(config: &Config) -> Result<Vec<String>, Box<dyn Error>> {
+    if config.patterns.is_empty() && config.pattern_files.is_empty() {
+        return Ok(vec![config.query.clone()]);
+    }
+    let mut patterns = config.patterns.clone();
+    for path in &config.pattern_files {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        patterns.extend(
+            lines(&contents)
+                .map(|(_, line)| line)
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        );
+    }
+    Ok(patterns)
 }
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
-        .collect()
+#[cfg(not(target_os = "linux"))]
+pub fn watch(
+    _config: &Config,
+    _matcher: &Matcher,
+    _patterns: Vec<String>,
+) -> Result<Outcome, Box<dyn Error>> {
+    Err("--watch is only supported on Linux".into())
+}
+
+/// Colours are taken from `MINIGREP_COLORS` when highlighting is enabled.
+fn colors(choice: ColorChoice) -> Option<Colors> {
+    if !choice.enabled() {
+        return None;
+    }
+    let spec = env::var("MINIGREP_COLORS").unwrap_or_default();
+    Some(Colors::from_spec(&spec))
+}
+
+/// Searches a single file and prints the matching lines. The path `-` reads
+/// standard input. With `decompress`, compressed inputs are decoded on the
+/// fly and reported under their own path.
+pub(crate) fn search_file<W: Write>(
+    path: &Path,
+    matcher: &Matcher,
+    config: &Config,
+    printer: &mut Printer<W>,
+) -> Result<Stats, Box<dyn Error>> {
+    if path == Path::new(STDIN_NAME) {
+        let stdin = io::stdin();
+        let reader = BufReader::with_capacity(READ_BUFFER, stdin.lock());
+        let path = Path::new("(standard input)");
+        if config.decompress {
+            return search_input(path, maybe_decompress(reader)?, matcher, config, printer);
+        }
+        return search_input(path, reader, matcher, config, printer);
+    }
+    let reader = BufReader::with_capacity(READ_BUFFER, File::open(path)?);
+    if config.decompress {
+        return search_input(path, maybe_decompress(reader)?, matcher, config, printer);
+    }
+    search_input(path, reader, matcher, config, printer)
 }
 
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    contents
-        .lines()
-        .filter(|line| line.to_lowercase().contains(&query))
+/// Streams an input through the matcher. When printing lines, binary inputs
+/// are either skipped or summarised with a single "Binary file matches"
+/// line; the other output modes treat them like any other input.
+fn search_input<R: BufRead, W: Write>(
+    path: &Path,
+    reader: R,
+    matcher: &Matcher,
+    config: &Config,
+    printer: &mut Printer<W>,
+) -> Result<Stats, Box<dyn Error>> {
+    if config.mode != OutputMode::Lines {
+        return Ok(search_reader(
+            matcher,
+            config.invert,
+            config.replace.as_deref(),
+            path,
+            reader,
+            printer,
+        )?);
+    }
+    let (binary, reader) = sniff_binary(reader)?;
+    if binary {
+        let mut stats = Stats::default();
+        if config.skip_binary {
+            return Ok(stats);
+        }
+        if reader_matches(matcher, config.invert, reader)? {
+            printer.print_binary(path)?;
+            stats.matched_lines = 1;
+        }
+        return Ok(stats);
+    }
+
+    Ok(search_reader(
+        matcher,
+        config.invert,
+        config.replace.as_deref(),
+        path,
+        reader,
+        printer,
+    )?)
+}
+
+/// An input with its first block read back in front of the rest.
+type Sniffed<R> = io::Chain<Cursor<Vec<u8>>, R>;
+
+/// Reads the first block of the input, which from pipes and decoders can
+/// take several reads, and tells whether it is binary. The reader returned
+/// yields the whole input again.
+pub(crate) fn sniff_binary<R: BufRead>(mut reader: R) -> io::Result<(bool, Sniffed<R>)> {
+    let mut head = Vec::with_capacity(BINARY_BLOCK);
+    reader
+        .by_ref()
+        .take(BINARY_BLOCK as u64)
+        .read_to_end(&mut head)?;
+    Ok((is_binary(&head), Cursor::new(head).chain(reader)))
+}
+
+/// A file is treated as binary when its first block contains a NUL byte.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_BLOCK)].contains(&0)
+}
+
+/// A line of the searched contents that was accepted by the matcher.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    /// Line number within the contents, starting at 1.
+    pub line_number: usize,
+    /// Byte range of the line within the contents, excluding the line
+    /// terminator. `span.start` is the byte offset of the line.
+    pub span: Range<usize>,
+    /// Byte range of each hit relative to the start of the line.
+    pub submatches: Vec<Range<usize>>,
+    /// Index of the pattern behind each hit, in the same order as
+    /// `submatches`. Always 0 when searching for a single pattern.
+    pub patterns: Vec<usize>,
+    /// Edit distance of the closest hit when searching approximately.
+    pub distance: Option<usize>,
+    pub line: &'a str,
+}
+
+impl<'a> Match<'a> {
+    /// Byte offset of the start of the line within the contents.
+    pub fn byte_offset(&self) -> usize {
+        self.span.start
+    }
+
+    /// 1-based column of the first hit on the line, counted in bytes.
+    pub fn column(&self) -> Option<usize> {
+        self.submatches.first().map(|m| m.start + 1)
+    }
+}
+
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    search_with(&Matcher::literal(query, true), contents)
+}
+
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    search_with(&Matcher::literal(query, false), contents)
+}
+
+/// Returns the lines of `contents` accepted by the matcher. Case sensitive
+/// literal queries scan the whole buffer with `Finder` instead of going line
+/// by line.
+pub fn search_with<'a>(matcher: &Matcher, contents: &'a str) -> Vec<Match<'a>> {
+    if let Some(finder) = matcher.finder() {
+        return finder.search(contents);
+    }
+
+    lines(contents)
+        .enumerate()
+        .filter_map(|(i, (span, line))| {
+            let (found, distance) = matcher.find_scored(line);
+            let (submatches, patterns): (Vec<_>, Vec<_>) = found.into_iter().unzip();
+            if submatches.is_empty() {
+                return None;
+            }
+            Some(Match {
+                line_number: i + 1,
+                span,
+                distance,
+                submatches,
+                patterns,
+                line,
+            })
+        })
         .collect()
 }
 
+/// Splits the contents into lines the same way as `str::lines`, yielding the
+/// byte range of each line alongside it.
+pub fn lines(contents: &str) -> impl Iterator<Item = (Range<usize>, &str)> {
+    let mut offset = 0;
+    contents.split_inclusive('\n').map(move |raw| {
+        let start = offset;
+        offset += raw.len();
+        let line = match raw.strip_suffix('\n') {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line),
+            None => raw,
+        };
+        (start..start + line.len(), line)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn lines_of(matches: Vec<Match<'_>>) -> Vec<&str> {
+        matches.into_iter().map(|m| m.line).collect()
+    }
+
     #[test]
     fn case_sensitive() {
         let query = "duct";
@@ -73,7 +512,17 @@ Rust:
 safe, fast, productive.
 Pick three.";
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+        assert_eq!(
+            vec![Match {
+                line_number: 2,
+                span: 6..29,
+                submatches: vec![Range { start: 15, end: 19 }],
+                patterns: vec![0],
+                distance: None,
+                line: "safe, fast, productive."
+            }],
+            search(query, contents)
+        );
     }
 
     #[test]
@@ -87,7 +536,102 @@ Trust me.";
 
         assert_eq!(
             vec!["Rust:", "Trust me."],
-            search_case_insensitive(query, contents)
+            lines_of(search_case_insensitive(query, contents))
+        );
+    }
+
+    #[test]
+    fn exit_codes() {
+        let outcome = |matched, errors| Outcome { matched, errors };
+        assert_eq!(
+            EXIT_MATCH,
+            outcome(true, false).exit_code(OutputMode::Lines)
+        );
+        assert_eq!(
+            EXIT_NO_MATCH,
+            outcome(false, false).exit_code(OutputMode::Lines)
+        );
+        assert_eq!(EXIT_ERROR, outcome(true, true).exit_code(OutputMode::Lines));
+        assert_eq!(
+            EXIT_ERROR,
+            outcome(false, true).exit_code(OutputMode::Quiet)
+        );
+        assert_eq!(EXIT_MATCH, outcome(true, true).exit_code(OutputMode::Quiet));
+    }
+
+    #[test]
+    fn binary_detection() {
+        assert!(!is_binary(b"Rust:\nsafe, fast, productive."));
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\x00"));
+
+        // only the first block is inspected
+        let mut late = vec![b'a'; BINARY_BLOCK];
+        late.push(0);
+        assert!(!is_binary(&late));
+    }
+
+    /// Hands out one byte per read, as a slow pipe might.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.is_empty()) {
+                (Some((&byte, rest)), false) => {
+                    buf[0] = byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn binary_detection_reads_the_whole_block() {
+        let mut input = b"text before the NUL".to_vec();
+        input.push(0);
+        let (binary, mut reader) = sniff_binary(BufReader::new(Trickle(&input))).unwrap();
+        assert!(binary);
+        let mut all = Vec::new();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(input, all);
+
+        let (binary, _) = sniff_binary(BufReader::new(Trickle(b"just text"))).unwrap();
+        assert!(!binary);
+    }
+
+    #[test]
+    fn regex() {
+        let matcher = Matcher::regex(r"^(Rust|Pick)\b", true).unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        assert_eq!(
+            vec!["Rust:", "Pick three."],
+            lines_of(search_with(&matcher, contents))
+        );
+    }
+
+    #[test]
+    fn line_spans() {
+        let contents = "Rust:\r\nsafe\n\nfast";
+        assert_eq!(
+            vec![
+                (0..5, "Rust:"),
+                (7..11, "safe"),
+                (12..12, ""),
+                (13..17, "fast")
+            ],
+            lines(contents).collect::<Vec<_>>()
+        );
+        // a lone carriage return only ends a line before a line feed
+        assert_eq!(
+            vec![(0..3, "a\rb"), (4..6, "c\r")],
+            lines("a\rb\nc\r").collect::<Vec<_>>()
         );
+        assert!(lines("a\rb\nc\r").map(|(_, l)| l).eq("a\rb\nc\r".lines()));
     }
 }
@@ -0,0 +1,167 @@
+use crate::Match;
+use memchr::{memchr, memchr_iter, memrchr};
+
+/// Bytes ordered from most to least common in source code and prose. Bytes
+/// missing from the list are assumed to be rarer than all of them.
+const COMMON: &[u8] = b" etaoinsrlhdcu\tpmf_gy,.()=bwv;:\"'k/-0x1ETSAIRCNL{}2jq*";
+
+/// Searches a whole buffer for a literal needle. Rather than testing every
+/// position, the rarest byte of the needle is located with `memchr`, which
+/// skips through the haystack many bytes at a time, and the rest of the
+/// needle is only compared where that byte occurs.
+pub struct Finder<'n> {
+    needle: &'n [u8],
+    /// The rarest byte of the needle and its offset within it.
+    rare: u8,
+    rare_offset: usize,
+}
+
+impl<'n> Finder<'n> {
+    /// Returns `None` for an empty needle or one spanning several lines,
+    /// which can't be searched for a line at a time.
+    pub fn new(needle: &'n str) -> Option<Finder<'n>> {
+        let needle = needle.as_bytes();
+        if needle.is_empty() || needle.iter().any(|&b| b == b'\n' || b == b'\r') {
+            return None;
+        }
+        let (rare_offset, &rare) = needle
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &b)| frequency(b))
+            .unwrap();
+        Some(Finder {
+            needle,
+            rare,
+            rare_offset,
+        })
+    }
+
+    /// Returns the start of the first occurrence of the needle at or after
+    /// `start`.
+    pub fn find(&self, haystack: &[u8], start: usize) -> Option<usize> {
+        let mut at = start + self.rare_offset;
+        while at < haystack.len() {
+            let i = at + memchr(self.rare, &haystack[at..])?;
+            let begin = i - self.rare_offset;
+            if haystack[begin..].starts_with(self.needle) {
+                return Some(begin);
+            }
+            at = i + 1;
+        }
+        None
+    }
+
+    /// Finds every line of `contents` containing the needle. Line boundaries
+    /// are only looked for around each hit, and line numbers are counted
+    /// with `memchr` over the skipped stretches.
+    pub fn search<'a>(&self, contents: &'a str) -> Vec<Match<'a>> {
+        let bytes = contents.as_bytes();
+        let mut matches = Vec::new();
+        let mut line_number = 1;
+        let mut counted = 0;
+        let mut at = 0;
+
+        while let Some(hit) = self.find(bytes, at) {
+            let start = memrchr(b'\n', &bytes[..hit]).map_or(0, |i| i + 1);
+            let end = memchr(b'\n', &bytes[hit..]).map_or(bytes.len(), |i| hit + i);
+            line_number += memchr_iter(b'\n', &bytes[counted..start]).count();
+            counted = start;
+
+            // the needle has no '\r', so a hit never covers the terminator,
+            // which a final line without '\n' doesn't have
+            let text_end = match bytes[..end].last() {
+                Some(b'\r') if end > start && end < bytes.len() => end - 1,
+                _ => end,
+            };
+            let mut submatches = Vec::new();
+            let mut next = hit;
+            while let Some(i) = self.find(&bytes[..text_end], next) {
+                submatches.push(i - start..i - start + self.needle.len());
+                next = i + self.needle.len();
+            }
+
+            matches.push(Match {
+                line_number,
+                span: start..text_end,
+                patterns: vec![0; submatches.len()],
+                submatches,
+                distance: None,
+                line: &contents[start..text_end],
+            });
+            at = end + 1;
+        }
+        matches
+    }
+}
+
+fn frequency(b: u8) -> usize {
+    COMMON
+        .iter()
+        .position(|&c| c == b)
+        .map_or(0, |i| COMMON.len() - i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines;
+
+    /// The line at a time search that `Finder::search` replaces.
+    fn line_by_line<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+        lines(contents)
+            .enumerate()
+            .filter(|(_, (_, line))| line.contains(query))
+            .map(|(i, (span, line))| {
+                let submatches: Vec<_> = line
+                    .match_indices(query)
+                    .map(|(j, m)| j..j + m.len())
+                    .collect();
+                Match {
+                    line_number: i + 1,
+                    span,
+                    patterns: vec![0; submatches.len()],
+                    submatches,
+                    distance: None,
+                    line,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn picks_rare_byte() {
+        let finder = Finder::new("the quiz").unwrap();
+        assert_eq!(b'z', finder.rare);
+        assert_eq!(7, finder.rare_offset);
+        assert!(Finder::new("").is_none());
+        assert!(Finder::new("two\nlines").is_none());
+    }
+
+    #[test]
+    fn find() {
+        let finder = Finder::new("zz").unwrap();
+        assert_eq!(Some(2), finder.find(b"z zzz", 0));
+        assert_eq!(Some(3), finder.find(b"z zzz", 3));
+        assert_eq!(None, finder.find(b"z zzz", 4));
+        assert_eq!(None, finder.find(b"z", 0));
+    }
+
+    #[test]
+    fn agrees_with_line_search() {
+        let contents = "\
+Rust:\r
+safe, fast, productive.
+\r
+Pick three. Trust me, fast fast
+fast\r";
+        for query in &["fast", "Rust", "t", ", ", "st fa", "fast\u{e9}"] {
+            let finder = Finder::new(query).unwrap();
+            assert_eq!(
+                line_by_line(query, contents),
+                finder.search(contents),
+                "query {:?}",
+                query
+            );
+        }
+    }
+}
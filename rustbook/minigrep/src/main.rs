@@ -1,16 +1,66 @@
 use std::env;
 use std::process;
 
-use minigrep::Config;
+use minigrep::{Command, Config, Settings};
 
 fn main() {
-    let config = Config::new(env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
-    });
+    let config = match minigrep::parse_args_with(env::args_os(), &Settings::load()) {
+        Ok(Command::Search(config)) => config,
+        Ok(Command::IndexBuild(config)) => build_index(config),
+        Ok(Command::IndexSearch(config)) => {
+            let mode = config.mode;
+            match minigrep::search_index(&config) {
+                Ok(outcome) => process::exit(outcome.exit_code(mode)),
+                Err(e) => {
+                    eprintln!("Application error: {}", e);
+                    process::exit(minigrep::EXIT_ERROR);
+                }
+            }
+        }
+        Ok(Command::DebugConfig(report)) => {
+            print!("{}", report);
+            return;
+        }
+        Ok(Command::Help) => {
+            print!("{}", minigrep::help());
+            return;
+        }
+        Ok(Command::Version) => {
+            print!("{}", minigrep::version());
+            return;
+        }
+        Err(err) => {
+            eprintln!("Problem parsing arguments: {}", err);
+            eprintln!("Try 'minigrep --help' for more information.");
+            process::exit(err.exit_code());
+        }
+    };
 
-    if let Err(e) = minigrep::run(config) {
-        eprintln!("Application error: {}", e);
-        process::exit(1);
+    let mode = config.mode;
+    match minigrep::run(config) {
+        Ok(outcome) => process::exit(outcome.exit_code(mode)),
+        Err(e) => {
+            eprintln!("Application error: {}", e);
+            process::exit(minigrep::EXIT_ERROR);
+        }
+    }
+}
+
+fn build_index(config: Config) -> ! {
+    match minigrep::build_index(&config) {
+        Ok(stats) => {
+            println!(
+                "Indexed {} files ({} read, {} removed)",
+                stats.files, stats.read, stats.removed
+            );
+            process::exit(match stats.errors {
+                true => minigrep::EXIT_ERROR,
+                false => 0,
+            });
+        }
+        Err(e) => {
+            eprintln!("Application error: {}", e);
+            process::exit(minigrep::EXIT_ERROR);
+        }
     }
 }
@@ -0,0 +1,658 @@
+use crate::{Finder, Fuzzy};
+use aho_corasick::{AhoCorasick, Input, MatchKind};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashSet;
+use std::error::Error;
+use std::ops::Range;
+
+/// Decides whether a line of input matches the query. Literal and regular
+/// expression searches both go through this type so that `run` only needs a
+/// single search path.
+pub enum Matcher {
+    /// Plain substring search. When case insensitive, the query is stored
+    /// case folded and each line is folded a character at a time while
+    /// comparing, so no copy of the line is made.
+    Literal { query: String, case_sensitive: bool },
+    /// Regular expression search supporting anchors, character classes,
+    /// alternation and capture groups.
+    Regex(Regex),
+    /// Many literal patterns searched for at once with an Aho-Corasick
+    /// automaton, preferring the longest pattern at each position.
+    Literals(AhoCorasick),
+    /// Several regular expressions combined into one alternation. `groups`
+    /// holds the capture group wrapping each pattern, which tells which one
+    /// matched, and `list` each pattern compiled alone, which replacement
+    /// templates are expanded against.
+    Regexes {
+        re: Regex,
+        groups: Vec<usize>,
+        list: Vec<Regex>,
+    },
+    /// Several regular expressions searched for one at a time, for patterns
+    /// that can't be combined because they name groups alike. The leftmost
+    /// match wins, and the earlier pattern among those starting together.
+    RegexList(Vec<Regex>),
+    /// Only accepts matches of the inner matcher that are whole words: not
+    /// preceded or followed by a word character.
+    Word(Box<Matcher>),
+    /// Approximate search for a single pattern, accepting text within a
+    /// number of edits of it.
+    Fuzzy(Box<Fuzzy>),
+}
+
+impl Matcher {
+    /// Constructs a literal substring matcher. Case insensitive matching
+    /// uses full Unicode case folding, so `ß` matches `SS`.
+    pub fn literal(query: &str, case_sensitive: bool) -> Matcher {
+        let query = if case_sensitive {
+            query.to_string()
+        } else {
+            query.chars().flat_map(fold).collect()
+        };
+        Matcher::Literal {
+            query,
+            case_sensitive,
+        }
+    }
+
+    /// Compiles the query as a regular expression. Case insensitivity is
+    /// applied by the regex engine rather than by lowercasing the input; it
+    /// only knows simple case folding, where one character folds to one.
+    pub fn regex(query: &str, case_sensitive: bool) -> Result<Matcher, regex::Error> {
+        let re = RegexBuilder::new(query)
+            .case_insensitive(!case_sensitive)
+            .build()?;
+        Ok(Matcher::Regex(re))
+    }
+
+    /// Constructs a matcher for any of several literal patterns, reporting
+    /// which one each match came from. The automaton only folds ASCII case,
+    /// so case insensitive non-ASCII patterns go through the regex engine.
+    pub fn literals(patterns: &[String], case_sensitive: bool) -> Result<Matcher, Box<dyn Error>> {
+        if let [pattern] = patterns {
+            return Ok(Matcher::literal(pattern, case_sensitive));
+        }
+        if !case_sensitive && !patterns.iter().all(|p| p.is_ascii()) {
+            let escaped: Vec<String> = patterns.iter().map(|p| regex::escape(p)).collect();
+            return Ok(Matcher::regexes(&escaped, case_sensitive)?);
+        }
+        let automaton = AhoCorasick::builder()
+            .ascii_case_insensitive(!case_sensitive)
+            .match_kind(MatchKind::LeftmostLongest)
+            .build(patterns)?;
+        Ok(Matcher::Literals(automaton))
+    }
+
+    /// Compiles several regular expressions into one matcher, reporting
+    /// which one each match came from. Replacement templates are expanded
+    /// against the pattern that matched, so `$1` is always that pattern's
+    /// own first group. Patterns naming the same group can't share an
+    /// alternation and are searched for one at a time instead. No patterns
+    /// at all match nothing.
+    pub fn regexes(patterns: &[String], case_sensitive: bool) -> Result<Matcher, regex::Error> {
+        if let [pattern] = patterns {
+            return Matcher::regex(pattern, case_sensitive);
+        }
+        let mut list = Vec::with_capacity(patterns.len());
+        let mut groups = Vec::with_capacity(patterns.len());
+        let mut names = HashSet::new();
+        let mut clash = false;
+        let mut next = 1;
+        for pattern in patterns {
+            // compiled alone first so that an unbalanced pattern can't
+            // escape its group, and so errors name the pattern at fault
+            let re = RegexBuilder::new(pattern)
+                .case_insensitive(!case_sensitive)
+                .build()?;
+            for name in re.capture_names().flatten() {
+                clash |= !names.insert(name.to_string());
+            }
+            groups.push(next);
+            next += re.captures_len();
+            list.push(re);
+        }
+        if clash || patterns.is_empty() {
+            return Ok(Matcher::RegexList(list));
+        }
+        let alternation: Vec<String> = patterns.iter().map(|p| format!("({})", p)).collect();
+        let re = RegexBuilder::new(&alternation.join("|"))
+            .case_insensitive(!case_sensitive)
+            .build()?;
+        Ok(Matcher::Regexes { re, groups, list })
+    }
+
+    /// Constructs an approximate matcher accepting text within `max`
+    /// insertions, deletions or substitutions of the query.
+    pub fn fuzzy(query: &str, max: usize, case_sensitive: bool) -> Result<Matcher, Box<dyn Error>> {
+        Ok(Matcher::Fuzzy(Box::new(Fuzzy::new(
+            query,
+            max,
+            case_sensitive,
+        )?)))
+    }
+
+    /// Restricts the matcher to whole words, as `grep -w` does.
+    pub fn whole_word(self) -> Matcher {
+        match self {
+            Matcher::Word(_) => self,
+            _ => Matcher::Word(Box::new(self)),
+        }
+    }
+
+    /// A `Finder` for case sensitive literal queries, which can scan a whole
+    /// buffer instead of a line at a time. The raw bytes of a line contain
+    /// the query whenever the lossily decoded line does, unless the query
+    /// itself holds the replacement character.
+    pub fn finder(&self) -> Option<Finder<'_>> {
+        match self {
+            Matcher::Literal {
+                query,
+                case_sensitive: true,
+            } if !query.contains(char::REPLACEMENT_CHARACTER) => Finder::new(query),
+            _ => None,
+        }
+    }
+
+    /// Returns true when the line contains a match for the query.
+    pub fn is_match(&self, line: &str) -> bool {
+        match self {
+            Matcher::Literal {
+                query,
+                case_sensitive: true,
+            } => line.contains(query.as_str()),
+            Matcher::Literal {
+                query,
+                case_sensitive: false,
+            } => find_folded(query, line, 0).is_some(),
+            Matcher::Regex(re) => re.is_match(line),
+            Matcher::Literals(automaton) => automaton.is_match(line),
+            Matcher::Regexes { re, .. } => re.is_match(line),
+            Matcher::RegexList(list) => list.iter().any(|re| re.is_match(line)),
+            Matcher::Word(_) => self.find_at(line, 0).is_some(),
+            Matcher::Fuzzy(fuzzy) => fuzzy.is_match(line),
+        }
+    }
+
+    /// Like `find_patterns`, also returning the edit distance of the closest
+    /// match when searching approximately, or `None` for exact matchers and
+    /// lines without any.
+    pub fn find_scored(&self, line: &str) -> (Vec<(Range<usize>, usize)>, Option<usize>) {
+        match self {
+            Matcher::Fuzzy(_) | Matcher::Word(_) => self.find_all(line),
+            _ => (self.find_patterns(line), None),
+        }
+    }
+
+    /// Returns the byte range within the line of every non-overlapping match.
+    pub fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        match self {
+            Matcher::Literal {
+                query,
+                case_sensitive: true,
+            } => line
+                .match_indices(query.as_str())
+                .map(|(i, m)| i..i + m.len())
+                .collect(),
+            Matcher::Regex(re) | Matcher::Regexes { re, .. } => {
+                re.find_iter(line).map(|m| m.range()).collect()
+            }
+            Matcher::Literals(automaton) => automaton.find_iter(line).map(|m| m.range()).collect(),
+            _ => self
+                .find_patterns(line)
+                .into_iter()
+                .map(|(m, _)| m)
+                .collect(),
+        }
+    }
+
+    /// Like `find_iter`, but also returns the index of the pattern that
+    /// produced each match. Single pattern matchers always report 0.
+    pub fn find_patterns(&self, line: &str) -> Vec<(Range<usize>, usize)> {
+        match self {
+            Matcher::Literal {
+                case_sensitive: true,
+                ..
+            }
+            | Matcher::Regex(_) => self.find_iter(line).into_iter().map(|m| (m, 0)).collect(),
+            Matcher::Literals(automaton) => automaton
+                .find_iter(line)
+                .map(|m| (m.range(), m.pattern().as_usize()))
+                .collect(),
+            Matcher::Regexes { re, groups, .. } => re
+                .captures_iter(line)
+                .map(|caps| (caps.get(0).unwrap().range(), which_group(&caps, groups)))
+                .collect(),
+            _ => self.find_all(line).0,
+        }
+    }
+
+    /// Finds every match with `find_at`, along with the closest distance.
+    fn find_all(&self, line: &str) -> (Vec<(Range<usize>, usize)>, Option<usize>) {
+        let mut found = Vec::new();
+        let mut closest: Option<usize> = None;
+        let mut at = 0;
+        while let Some((m, pattern, distance)) = self.find_at(line, at) {
+            // step over an empty match so the search makes progress
+            at = match m.is_empty() {
+                true => next_char(line, m.end),
+                false => m.end,
+            };
+            found.push((m, pattern));
+            if let Some(distance) = distance {
+                closest = Some(closest.map_or(distance, |c| c.min(distance)));
+            }
+            if at > line.len() {
+                break;
+            }
+        }
+        (found, closest)
+    }
+
+    /// Returns the first match starting at or after `start`, which must be a
+    /// character boundary, along with the index of the pattern it came from
+    /// and, when searching approximately, its distance.
+    fn find_at(&self, line: &str, start: usize) -> Option<(Range<usize>, usize, Option<usize>)> {
+        let (m, pattern) = match self {
+            Matcher::Literal {
+                query,
+                case_sensitive: true,
+            } => line[start..]
+                .find(query.as_str())
+                .map(|i| (start + i..start + i + query.len(), 0)),
+            Matcher::Literal {
+                query,
+                case_sensitive: false,
+            } => find_folded(query, line, start).map(|m| (m, 0)),
+            Matcher::Regex(re) => re.find_at(line, start).map(|m| (m.range(), 0)),
+            Matcher::Literals(automaton) => automaton
+                .find(Input::new(line).range(start..))
+                .map(|m| (m.range(), m.pattern().as_usize())),
+            Matcher::Regexes { re, groups, .. } => re
+                .captures_at(line, start)
+                .map(|caps| (caps.get(0).unwrap().range(), which_group(&caps, groups))),
+            Matcher::RegexList(list) => list
+                .iter()
+                .enumerate()
+                .filter_map(|(i, re)| re.find_at(line, start).map(|m| (m.range(), i)))
+                .min_by_key(|(m, _)| m.start),
+            Matcher::Word(inner) => {
+                // a rejected candidate may overlap a whole word starting
+                // later, so retry one character further on
+                let mut at = start;
+                while at <= line.len() {
+                    let found = inner.find_at(line, at)?;
+                    if is_whole_word(line, &found.0) {
+                        return Some(found);
+                    }
+                    at = next_char(line, found.0.start);
+                }
+                None
+            }
+            Matcher::Fuzzy(fuzzy) => {
+                return fuzzy
+                    .find_at(line, start)
+                    .map(|(m, distance)| (m, 0, Some(distance)))
+            }
+        }?;
+        Some((m, pattern, None))
+    }
+
+    /// Replaces every match in the line with the template, returning the new
+    /// line and the byte range each replacement occupies within it. Regex
+    /// templates may refer to capture groups as `$1` or `${name}`; literal
+    /// templates are inserted as they are.
+    pub fn replace(&self, line: &str, template: &str) -> (String, Vec<Range<usize>>) {
+        let inner = match self {
+            Matcher::Word(inner) => inner.as_ref(),
+            _ => self,
+        };
+        let regex = |pattern: usize| match inner {
+            Matcher::Regex(re) => Some(re),
+            Matcher::Regexes { list, .. } | Matcher::RegexList(list) => list.get(pattern),
+            _ => None,
+        };
+
+        let mut out = String::with_capacity(line.len());
+        let mut spans = Vec::new();
+        let mut last = 0;
+        for (m, pattern) in self.find_patterns(line) {
+            out.push_str(&line[last..m.start]);
+            let start = out.len();
+            match regex(pattern).and_then(|re| re.captures_at(line, m.start)) {
+                Some(caps) => caps.expand(template, &mut out),
+                None => out.push_str(template),
+            }
+            spans.push(start..out.len());
+            last = m.end;
+        }
+        out.push_str(&line[last..]);
+        (out, spans)
+    }
+}
+
+/// Index of the pattern whose wrapping group took part in the match.
+fn which_group(caps: &regex::Captures<'_>, groups: &[usize]) -> usize {
+    groups
+        .iter()
+        .position(|&group| caps.get(group).is_some())
+        .unwrap_or(0)
+}
+
+/// Decides the case sensitivity for `--smart-case`: sensitive only when the
+/// query contains an uppercase letter. In a regex, the letters of escapes
+/// such as `\W` or `\p{Greek}` don't count.
+pub fn has_uppercase(query: &str, regex: bool) -> bool {
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            if let Some('p' | 'P') = chars.next() {
+                if chars.as_str().starts_with('{') {
+                    chars.by_ref().find(|&c| c == '}');
+                } else {
+                    chars.next();
+                }
+            }
+            continue;
+        }
+        if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Full Unicode case folding of a single character, which may expand to
+/// several characters (`ß` folds to `ss`). Folding the uppercase form
+/// lowercases everything that uppercases to the same letters, covering
+/// final sigma and the compatibility forms like `ſ` and `µ`.
+///
+/// The dotted and dotless Turkish I's fold to a plain `i`, so a query
+/// matches whichever convention the text was written in.
+pub(crate) fn fold(c: char) -> impl Iterator<Item = char> {
+    c.to_uppercase()
+        .flat_map(char::to_lowercase)
+        .filter(move |&f| c != '\u{130}' || f != '\u{307}')
+}
+
+/// Finds the first occurrence of an already folded query in the line at or
+/// after `start`. Matches begin and end on character boundaries of the line,
+/// so `s` doesn't match half of `ß`.
+fn find_folded(query: &str, line: &str, start: usize) -> Option<Range<usize>> {
+    if query.is_empty() {
+        return Some(start..start);
+    }
+    line[start..].char_indices().find_map(|(i, _)| {
+        let at = start + i;
+        match_folded(query, &line[at..]).map(|len| at..at + len)
+    })
+}
+
+/// Returns the length of the prefix of `text` that folds to the query.
+fn match_folded(query: &str, text: &str) -> Option<usize> {
+    let mut query = query.chars();
+    for (i, c) in text.char_indices() {
+        for f in fold(c) {
+            if query.next() != Some(f) {
+                return None;
+            }
+        }
+        if query.as_str().is_empty() {
+            return Some(i + c.len_utf8());
+        }
+    }
+    None
+}
+
+/// Word characters are letters, digits and underscores from any script.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_whole_word(line: &str, m: &Range<usize>) -> bool {
+    let before = line[..m.start].chars().next_back();
+    let after = line[m.end..].chars().next();
+    !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+}
+
+/// Byte index of the character after the one at `at`, or one past the end
+/// of the line when `at` is the end.
+fn next_char(line: &str, at: usize) -> usize {
+    at + line[at..].chars().next().map_or(1, char::len_utf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_case_sensitive() {
+        let matcher = Matcher::literal("Rust", true);
+        assert!(matcher.is_match("Rust:"));
+        assert!(!matcher.is_match("trust me"));
+    }
+
+    #[test]
+    fn regex_anchors_and_classes() {
+        let matcher = Matcher::regex(r"^[A-Z]\w+:$", true).unwrap();
+        assert!(matcher.is_match("Rust:"));
+        assert!(!matcher.is_match("safe, fast, productive."));
+    }
+
+    #[test]
+    fn regex_alternation_and_groups() {
+        let matcher = Matcher::regex(r"(fast|three)\.?$", true).unwrap();
+        assert!(matcher.is_match("Pick three."));
+        assert!(!matcher.is_match("Rust:"));
+    }
+
+    #[test]
+    fn regex_case_insensitive() {
+        let matcher = Matcher::regex("^rust", false).unwrap();
+        assert!(matcher.is_match("Rust:"));
+        assert!(!matcher.is_match("Trust me."));
+    }
+
+    #[test]
+    fn find_spans() {
+        let matcher = Matcher::literal("st", true);
+        assert_eq!(vec![2..4, 8..10], matcher.find_iter("Rust, Rust"));
+
+        let matcher = Matcher::regex(r"\bR\w+", true).unwrap();
+        assert_eq!(vec![0..4, 6..10], matcher.find_iter("Rust, Rust"));
+    }
+
+    #[test]
+    fn find_spans_case_insensitive() {
+        let matcher = Matcher::literal("RUST", false);
+        assert_eq!(vec![1..5], matcher.find_iter("Trust me."));
+
+        // 'İ' is two bytes but lowercases to three
+        assert_eq!(vec![6..10], matcher.find_iter("İİ: rust"));
+    }
+
+    #[test]
+    fn case_folding() {
+        let matcher = Matcher::literal("STRASSE", false);
+        assert_eq!(vec![4..11], matcher.find_iter("Die Straße"));
+        assert!(Matcher::literal("straße", false).is_match("STRASSE"));
+
+        // half of an expansion is not a match
+        assert!(!Matcher::literal("s", false).is_match("ß"));
+
+        let matcher = Matcher::literal("ΣΟΦΟΣ", false);
+        // final sigma folds like any other sigma
+        assert!(matcher.is_match("σοφος"));
+
+        let matcher = Matcher::literal("İstanbul", false);
+        assert_eq!(vec![0..8, 9..18], matcher.find_iter("istanbul ıstanbul"));
+        assert!(Matcher::literal("ISTANBUL", false).is_match("İstanbul"));
+    }
+
+    #[test]
+    fn empty_query_matches_everywhere() {
+        let matcher = Matcher::literal("", false);
+        assert_eq!(vec![0..0, 1..1, 3..3], matcher.find_iter("aé"));
+    }
+
+    #[test]
+    fn whole_words() {
+        let matcher = Matcher::literal("rust", false).whole_word();
+        assert_eq!(vec![17..21], matcher.find_iter("Trust me, rust_y rust."));
+        assert!(!matcher.is_match("rustacean"));
+
+        // a rejected candidate doesn't hide a later overlapping word
+        let matcher = Matcher::literal("aa", true).whole_word();
+        assert_eq!(vec![4..6], matcher.find_iter("aaa aa"));
+
+        // word boundaries apply to every script
+        let matcher = Matcher::regex(r"ф\w+", true).unwrap().whole_word();
+        assert_eq!(vec![10..18, 19..29], matcher.find_iter("кафе, фото фильм"));
+
+        let matcher = Matcher::regex(r"(\w)o", true).unwrap().whole_word();
+        assert_eq!(
+            ("g t o? t!".to_string(), vec![0..1, 2..3, 7..8]),
+            matcher.replace("go to o? to!", "${1}")
+        );
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn many_literals() {
+        let matcher =
+            Matcher::literals(&patterns(&["unwrap", "expect", "unwrap_or"]), true).unwrap();
+        assert!(matches!(matcher, Matcher::Literals(_)));
+        assert_eq!(
+            vec![(2..11, 2), (14..20, 1)],
+            matcher.find_patterns("x.unwrap_or(y.expect(\"z\"))")
+        );
+        assert!(!matcher.is_match("Unwrap"));
+
+        let matcher = Matcher::literals(&patterns(&["unwrap", "expect"]), false).unwrap();
+        assert_eq!(vec![(0..6, 0)], matcher.find_patterns("UNWRAP"));
+
+        // the automaton only folds ASCII, so this goes through the regex engine
+        let matcher = Matcher::literals(&patterns(&["größe", "a.b"]), false).unwrap();
+        assert!(matches!(matcher, Matcher::Regexes { .. }));
+        assert_eq!(
+            vec![(0..7, 0), (8..11, 1)],
+            matcher.find_patterns("GRÖßE a.b axb")
+        );
+
+        let matcher = Matcher::literals(&patterns(&["rust"]), true).unwrap();
+        assert!(matches!(matcher, Matcher::Literal { .. }));
+    }
+
+    #[test]
+    fn many_regexes() {
+        let matcher = Matcher::regexes(&patterns(&[r"(\d)+", r"[a-z]+(!)"]), true).unwrap();
+        assert_eq!(
+            vec![(0..3, 0), (4..8, 1)],
+            matcher.find_patterns("123 wow!")
+        );
+        assert_eq!(
+            ("<3> <!>".to_string(), vec![0..3, 4..7]),
+            matcher.replace("123 wow!", "<$1>")
+        );
+        assert!(Matcher::regexes(&patterns(&["a)|(b"]), true).is_err());
+
+        let matcher = Matcher::regexes(&patterns(&["wow", r"\d+"]), true)
+            .unwrap()
+            .whole_word();
+        assert_eq!(vec![(8..10, 1)], matcher.find_patterns("wowsers 42"));
+    }
+
+    #[test]
+    fn regexes_naming_groups_alike() {
+        let matcher =
+            Matcher::regexes(&patterns(&[r"(?P<n>\d+)!", r"#(?P<n>\w+)", r"\d"]), true).unwrap();
+        assert!(matches!(matcher, Matcher::RegexList(_)));
+        assert_eq!(
+            vec![(0..4, 1), (5..8, 0), (9..10, 2)],
+            matcher.find_patterns("#tag 42! 7")
+        );
+        assert_eq!(
+            ("<tag> <42> <>".to_string(), vec![0..5, 6..10, 11..13]),
+            matcher.replace("#tag 42! 7", "<$n>")
+        );
+    }
+
+    #[test]
+    fn replacements_number_groups_within_their_pattern() {
+        let matcher = Matcher::regexes(&patterns(&[r"a(\d)", r"b(\d)"]), true).unwrap();
+        assert!(matches!(matcher, Matcher::Regexes { .. }));
+        assert_eq!(
+            ("X1 X2".to_string(), vec![0..2, 3..5]),
+            matcher.replace("a1 b2", "X$1")
+        );
+    }
+
+    #[test]
+    fn no_patterns_match_nothing() {
+        for matcher in [
+            Matcher::regexes(&[], true).unwrap(),
+            Matcher::literals(&[], true).unwrap(),
+            Matcher::literals(&[], false).unwrap(),
+        ] {
+            assert!(!matcher.is_match("anything"));
+            assert!(!matcher.is_match(""));
+            assert!(matcher.find_patterns("anything").is_empty());
+        }
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(!has_uppercase("rust", false));
+        assert!(has_uppercase("Rust", false));
+        assert!(!has_uppercase(r"\w+\p{Greek}\PL\S", true));
+        assert!(has_uppercase(r"\w+R", true));
+    }
+
+    #[test]
+    fn replace_literal() {
+        let matcher = Matcher::literal("rust", false);
+        assert_eq!(
+            ("$1 me, $1!".to_string(), vec![0..2, 7..9]),
+            matcher.replace("Rust me, rust!", "$1")
+        );
+    }
+
+    #[test]
+    fn replace_with_captures() {
+        let matcher = Matcher::regex(r"(?P<key>\w+)=(\w+)", true).unwrap();
+        assert_eq!(
+            ("let a: 1; let b: 2".to_string(), vec![0..8, 10..18]),
+            matcher.replace("a=1; b=2", "let ${key}: $2")
+        );
+    }
+
+    #[test]
+    fn fuzzy() {
+        let matcher = Matcher::fuzzy("config", 1, false).unwrap();
+        let line = "Confg loaded from the konfig file";
+        assert_eq!(vec![0..5, 22..28], matcher.find_iter(line));
+        assert_eq!(
+            (vec![(0..5, 0), (22..28, 0)], Some(1)),
+            matcher.find_scored(line)
+        );
+        assert_eq!(Some(0), matcher.find_scored("a config").1);
+        assert!(!matcher.is_match("cfg"));
+
+        let matcher = matcher.whole_word();
+        assert_eq!(vec![0..5, 22..28], matcher.find_iter(line));
+        assert!(!matcher.is_match("configure"));
+        assert_eq!(Some(1), matcher.find_scored(line).1);
+        assert_eq!(
+            (Vec::new(), None),
+            Matcher::literal("config", true).find_scored("settings")
+        );
+        assert_eq!(None, Matcher::literal("fig", true).find_scored(line).1);
+    }
+
+    #[test]
+    fn regex_invalid() {
+        assert!(Matcher::regex("(unclosed", true).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{search_file, Config, Matcher, Outcome, OutputMode, Printer, Stats};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::sync_channel;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// How many files each worker may search ahead of the next one to be
+/// written.
+const AHEAD_PER_JOB: usize = 4;
+
+/// Output rendered by a worker for a single file.
+struct FileOutput {
+    index: usize,
+    printer: Printer<Vec<u8>>,
+    result: Result<Stats, String>,
+}
+
+/// Hands out the indexes of the files to search, never more than `limit`
+/// past the next file to be written, so that a slow file holds back a
+/// bounded number of finished ones.
+struct Queue {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+    limit: usize,
+}
+
+struct QueueState {
+    next: usize,
+    written: usize,
+    closed: bool,
+}
+
+impl Queue {
+    fn new(limit: usize) -> Queue {
+        Queue {
+            state: Mutex::new(QueueState {
+                next: 0,
+                written: 0,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+            limit,
+        }
+    }
+
+    /// The next index, waiting while it is too far ahead. `None` once the
+    /// queue is closed.
+    fn take(&self) -> Option<usize> {
+        let state = self.state.lock().unwrap();
+        let mut state = self
+            .changed
+            .wait_while(state, |s| !s.closed && s.next >= s.written + self.limit)
+            .unwrap();
+        if state.closed {
+            return None;
+        }
+        state.next += 1;
+        Some(state.next - 1)
+    }
+
+    /// Records that every file before `written` has been written.
+    fn advance(&self, written: usize) {
+        self.state.lock().unwrap().written = written;
+        self.changed.notify_all();
+    }
+
+    /// Stops handing out indexes.
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+/// Searches the files using `config.jobs` worker threads, or one per CPU when
+/// it is zero. Workers take the next file from a shared counter and render
+/// its output into a buffer, which is sent back to this thread over a
+/// channel. Buffers are written in the order the paths were given, so the
+/// output is the same no matter how many workers are used. Workers only
+/// search a few files ahead of the next one to be written, so the buffers
+/// held back waiting for a slow file are bounded.
+///
+/// In quiet mode the search stops as soon as any file has a selected line.
+pub fn search_files<W: Write>(
+    paths: &[PathBuf],
+    matcher: &Matcher,
+    config: &Config,
+    printer: &mut Printer<W>,
+) -> io::Result<Outcome> {
+    let jobs = match config.jobs {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let jobs = jobs.min(paths.len()).max(1);
+
+    let quiet = config.mode == OutputMode::Quiet;
+    let mut outcome = Outcome::default();
+
+    if jobs == 1 {
+        for path in paths {
+            match search_file(path, matcher, config, printer) {
+                Ok(stats) => outcome.matched |= stats.matched_lines > 0,
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    outcome.errors = true;
+                }
+            }
+            if quiet && outcome.matched {
+                break;
+            }
+        }
+        return Ok(outcome);
+    }
+
+    let options = printer.options().clone();
+    let queue = Queue::new(jobs * AHEAD_PER_JOB);
+    let (sender, receiver) = sync_channel(jobs);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let queue = &queue;
+            let options = &options;
+            scope.spawn(move || {
+                while let Some(index) = queue.take() {
+                    let path = match paths.get(index) {
+                        Some(v) => v,
+                        None => break,
+                    };
+                    let mut file_printer = Printer::new(Vec::new(), options.clone());
+                    let result = search_file(path, matcher, config, &mut file_printer)
+                        .map_err(|e| e.to_string());
+                    if quiet && result.as_ref().is_ok_and(|stats| stats.matched_lines > 0) {
+                        queue.close();
+                    }
+                    let output = FileOutput {
+                        index,
+                        printer: file_printer,
+                        result,
+                    };
+                    // the receiver only hangs up when writing failed
+                    if sender.send(output).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Results arrive in completion order and are held back until every
+        // earlier file has been written.
+        let mut pending = HashMap::new();
+        let mut next_write = 0;
+        let written = receiver.into_iter().try_for_each(|result| {
+            pending.insert(result.index, result);
+            while let Some(result) = pending.remove(&next_write) {
+                match result.result {
+                    Ok(stats) => outcome.matched |= stats.matched_lines > 0,
+                    Err(e) => {
+                        eprintln!("{}: {}", paths[next_write].display(), e);
+                        outcome.errors = true;
+                    }
+                }
+                printer.append(result.printer)?;
+                next_write += 1;
+            }
+            queue.advance(next_write);
+            Ok(())
+        });
+        // wakes any worker still waiting for an index
+        queue.close();
+        written.map(|()| outcome)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrintOptions;
+    use std::fs;
+
+    #[test]
+    fn queue_stays_within_limit() {
+        let queue = Queue::new(2);
+        assert_eq!((Some(0), Some(1)), (queue.take(), queue.take()));
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| queue.take());
+            queue.advance(1);
+            assert_eq!(Some(2), waiting.join().unwrap());
+        });
+        let waiting = thread::scope(|scope| {
+            let waiting = scope.spawn(|| queue.take());
+            queue.close();
+            waiting.join().unwrap()
+        });
+        assert_eq!(None, waiting);
+    }
+
+    fn render(paths: &[PathBuf], jobs: usize) -> String {
+        let config = Config {
+            jobs,
+            ..Config::default()
+        };
+        let options = PrintOptions {
+            after: 1,
+            with_path: true,
+            line_number: true,
+            ..PrintOptions::default()
+        };
+        let mut printer = Printer::new(Vec::new(), options);
+        let matcher = Matcher::literal("needle", true);
+        search_files(paths, &matcher, &config, &mut printer).unwrap();
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn output_order_matches_serial_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = (0..50)
+            .map(|i| {
+                let path = dir.path().join(format!("{:02}.txt", i));
+                let lines = (0..i * 20)
+                    .map(|n| match n % 7 {
+                        0 => format!("needle {}", n),
+                        _ => format!("hay {}", n),
+                    })
+                    .collect::<Vec<_>>();
+                let mut contents = lines.join("\n");
+                if i % 5 == 1 {
+                    contents.push('\0');
+                }
+                fs::write(&path, contents).unwrap();
+                path
+            })
+            .collect();
+
+        let serial = render(&paths, 1);
+        assert!(serial.contains("--\n"));
+        assert!(serial.contains("Binary file"));
+        assert!(!serial.contains("--\nBinary file"));
+        assert_eq!(serial, render(&paths, 4));
+        assert_eq!(serial, render(&paths, 0));
+    }
+}
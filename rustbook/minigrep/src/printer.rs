@@ -0,0 +1,705 @@
+use crate::color::{paint, Colors};
+use crate::{Match, Stats};
+use serde_json::json;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// What is printed for each searched file.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputMode {
+    /// The selected lines themselves, with any requested context.
+    #[default]
+    Lines,
+    /// The number of selected lines.
+    Count,
+    /// The path of each file with at least one selected line.
+    FilesWithMatches,
+    /// The path of each file without any selected line.
+    FilesWithoutMatch,
+    /// Nothing at all; the result is only reported through the exit status.
+    Quiet,
+}
+
+impl OutputMode {
+    /// Whether searching a file can stop at its first selected line.
+    pub fn stops_at_first_match(self) -> bool {
+        match self {
+            OutputMode::Lines | OutputMode::Count => false,
+            OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch | OutputMode::Quiet => {
+                true
+            }
+        }
+    }
+}
+
+/// Controls what is written for each line of output.
+#[derive(Debug, Clone, Default)]
+pub struct PrintOptions {
+    /// What to print for each file. The remaining options only apply to
+    /// `OutputMode::Lines`.
+    pub mode: OutputMode,
+    /// Number of context lines printed before each match.
+    pub before: usize,
+    /// Number of context lines printed after each match.
+    pub after: usize,
+    /// Prefix each line with the path of the file it came from.
+    pub with_path: bool,
+    /// Prefix each line with its 1-based line number.
+    pub line_number: bool,
+    /// Prefix each line with the byte offset of its start within the file.
+    pub byte_offset: bool,
+    /// Prefix matching lines with the 1-based column of the first match.
+    pub column: bool,
+    /// Emit one JSON object per event instead of plain text. The prefix
+    /// options are ignored because every event carries all of its fields.
+    pub json: bool,
+    /// Highlight matches, paths and prefix fields with these colours.
+    pub colors: Option<Colors>,
+    /// The patterns searched for, indexed like `Match::patterns`. When set,
+    /// each matching line is prefixed with the patterns found on it and each
+    /// JSON submatch names its pattern.
+    pub patterns: Vec<String>,
+    /// Matching lines are printed with their matches replaced, so they
+    /// aren't labelled with the patterns or distance that matched.
+    pub replaced: bool,
+    /// Hold back lines matched approximately and print them by increasing
+    /// edit distance when `finish` is called, instead of as they are found.
+    pub rank: bool,
+}
+
+/// Writes search results to an output stream as they are found. When context
+/// is requested the printer remembers the last line it wrote, so that
+/// non-adjacent groups, including those from different files, are separated
+/// with `--` just like grep.
+///
+/// In JSON mode each file with matches produces a `begin` event, a `match`
+/// or `context` event per line and a closing `end` event with statistics.
+pub struct Printer<W: Write> {
+    out: W,
+    options: PrintOptions,
+    /// Whether anything has been written for an earlier group or file.
+    printed_group: bool,
+    /// Line number of the last line written for the current file.
+    last_line: Option<usize>,
+    /// Lines held back for ranking, rendered, with their distance.
+    ranked: Vec<(usize, Vec<u8>)>,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(out: W, options: PrintOptions) -> Printer<W> {
+        Printer {
+            out,
+            options,
+            printed_group: false,
+            last_line: None,
+            ranked: Vec::new(),
+        }
+    }
+
+    pub fn options(&self) -> &PrintOptions {
+        &self.options
+    }
+
+    /// Consumes the printer and returns the underlying output stream.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Starts a new file. In JSON mode the `begin` event is held back until
+    /// the first line is printed so that files without matches stay silent.
+    pub fn begin_file(&mut self, _path: &Path) -> io::Result<()> {
+        self.last_line = None;
+        Ok(())
+    }
+
+    /// Finishes a file. Depending on the mode this prints the count of
+    /// selected lines, the file's path, or the JSON `end` event.
+    pub fn end_file(&mut self, path: &Path, stats: Stats) -> io::Result<()> {
+        match self.options.mode {
+            OutputMode::Lines => {}
+            OutputMode::Count => {
+                if self.options.with_path {
+                    self.write_colored(|c| &c.path, &path.display())?;
+                    self.write_colored(|c| &c.separator, &':')?;
+                }
+                return writeln!(self.out, "{}", stats.matched_lines);
+            }
+            OutputMode::FilesWithMatches if stats.matched_lines > 0 => {
+                self.write_colored(|c| &c.path, &path.display())?;
+                return writeln!(self.out);
+            }
+            OutputMode::FilesWithoutMatch if stats.matched_lines == 0 => {
+                self.write_colored(|c| &c.path, &path.display())?;
+                return writeln!(self.out);
+            }
+            _ => return Ok(()),
+        }
+
+        if self.options.json && self.last_line.is_some() {
+            self.print_json(json!({
+                "type": "end",
+                "data": { "path": path.to_string_lossy(), "binary": false, "stats": stats_json(stats) },
+            }))?;
+        }
+        Ok(())
+    }
+
+    /// Reports a binary file that contains a match without printing its lines.
+    pub fn print_binary(&mut self, path: &Path) -> io::Result<()> {
+        if self.options.json {
+            self.print_json(
+                json!({ "type": "begin", "data": { "path": path.to_string_lossy() } }),
+            )?;
+            return self.print_json(json!({
+                "type": "end",
+                "data": { "path": path.to_string_lossy(), "binary": true, "stats": null },
+            }));
+        }
+        writeln!(self.out, "Binary file {} matches", path.display())
+    }
+
+    /// Writes the lines held back for ranking, closest first. Lines at the
+    /// same distance keep the order they were found in.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.ranked.sort_by_key(|&(distance, _)| distance);
+        for (_, line) in self.ranked.drain(..) {
+            self.out.write_all(&line)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the output another printer rendered for a single file, and
+    /// takes over the lines it held back for ranking. Its lines are separated
+    /// from earlier ones with `--` as if this printer had printed them, while
+    /// binary file notices never are.
+    pub fn append(&mut self, other: Printer<Vec<u8>>) -> io::Result<()> {
+        let context = self.options.before > 0 || self.options.after > 0;
+        if context && self.printed_group && other.printed_group && !self.options.json {
+            writeln!(self.out, "--")?;
+        }
+        self.printed_group |= other.printed_group;
+        self.ranked.extend(other.ranked);
+        self.out.write_all(&other.out)
+    }
+
+    /// Prints a matching line. Prefix fields are followed by `:`.
+    pub fn print_match(&mut self, path: &Path, m: &Match) -> io::Result<()> {
+        if self.options.mode != OutputMode::Lines {
+            return Ok(());
+        }
+        if let (true, Some(distance)) = (self.options.rank, m.distance) {
+            let options = PrintOptions {
+                rank: false,
+                ..self.options.clone()
+            };
+            let mut printer = Printer::new(Vec::new(), options);
+            printer.print_match(path, m)?;
+            self.ranked.push((distance, printer.into_inner()));
+            return Ok(());
+        }
+        self.start_line(path, m.line_number)?;
+        if self.options.json {
+            let submatches: Vec<_> = m
+                .submatches
+                .iter()
+                .zip(&m.patterns)
+                .map(|(s, &pattern)| {
+                    let mut submatch =
+                        json!({ "match": &m.line[s.clone()], "start": s.start, "end": s.end });
+                    if let Some(name) = self.options.patterns.get(pattern) {
+                        submatch["pattern"] = json!(name);
+                    }
+                    submatch
+                })
+                .collect();
+            let mut event = json!({
+                "type": "match",
+                "data": {
+                    "path": path.to_string_lossy(),
+                    "line_number": m.line_number,
+                    "absolute_offset": m.span.start,
+                    "text": m.line,
+                    "submatches": submatches,
+                },
+            });
+            if let Some(distance) = m.distance {
+                event["data"]["distance"] = json!(distance);
+            }
+            return self.print_json(event);
+        }
+        let mut found: Vec<&str> = Vec::new();
+        for name in m
+            .patterns
+            .iter()
+            .filter_map(|&i| self.options.patterns.get(i))
+        {
+            if !found.contains(&name.as_str()) {
+                found.push(name);
+            }
+        }
+        // approximate matches are labelled with their distance instead
+        let label = match m.distance {
+            _ if self.options.replaced => String::new(),
+            Some(distance) => format!("~{}", distance),
+            None => found.join(","),
+        };
+        self.print_line(
+            path,
+            ':',
+            m.line_number,
+            m.span.start,
+            m.column(),
+            Some(label.as_str()).filter(|label| !label.is_empty()),
+            m.line,
+            &m.submatches,
+        )
+    }
+
+    /// Prints a context line. Prefix fields are followed by `-`.
+    pub fn print_context(
+        &mut self,
+        path: &Path,
+        line_number: usize,
+        byte_offset: usize,
+        line: &str,
+    ) -> io::Result<()> {
+        if self.options.mode != OutputMode::Lines {
+            return Ok(());
+        }
+        self.start_line(path, line_number)?;
+        if self.options.json {
+            return self.print_json(json!({
+                "type": "context",
+                "data": {
+                    "path": path.to_string_lossy(),
+                    "line_number": line_number,
+                    "absolute_offset": byte_offset,
+                    "text": line,
+                },
+            }));
+        }
+        self.print_line(path, '-', line_number, byte_offset, None, None, line, &[])
+    }
+
+    /// Writes whatever has to come before a line: the JSON `begin` event for
+    /// the first line of a file, or a `--` separator when the line does not
+    /// directly follow the previous one.
+    fn start_line(&mut self, path: &Path, line_number: usize) -> io::Result<()> {
+        let first_in_file = self.last_line.is_none();
+        let adjacent = self.last_line.is_some_and(|last| line_number == last + 1);
+        self.last_line = Some(line_number);
+
+        if self.options.json {
+            if first_in_file {
+                self.print_json(
+                    json!({ "type": "begin", "data": { "path": path.to_string_lossy() } }),
+                )?;
+            }
+            return Ok(());
+        }
+
+        let context = self.options.before > 0 || self.options.after > 0;
+        if context && self.printed_group && !adjacent {
+            writeln!(self.out, "--")?;
+        }
+        self.printed_group = true;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn print_line(
+        &mut self,
+        path: &Path,
+        sep: char,
+        line_number: usize,
+        byte_offset: usize,
+        column: Option<usize>,
+        label: Option<&str>,
+        line: &str,
+        submatches: &[Range<usize>],
+    ) -> io::Result<()> {
+        if self.options.with_path {
+            self.write_colored(|c| &c.path, &path.display())?;
+            self.write_colored(|c| &c.separator, &sep)?;
+        }
+        if self.options.line_number {
+            self.write_colored(|c| &c.line_number, &line_number)?;
+            self.write_colored(|c| &c.separator, &sep)?;
+        }
+        if let (true, Some(column)) = (self.options.column, column) {
+            self.write_colored(|c| &c.column, &column)?;
+            self.write_colored(|c| &c.separator, &sep)?;
+        }
+        if self.options.byte_offset {
+            self.write_colored(|c| &c.byte_offset, &byte_offset)?;
+            self.write_colored(|c| &c.separator, &sep)?;
+        }
+        if let Some(label) = label {
+            self.write_colored(|c| &c.matched, &label)?;
+            self.write_colored(|c| &c.separator, &sep)?;
+        }
+
+        let mut written = 0;
+        if self.options.colors.is_some() {
+            for span in submatches.iter().filter(|span| !span.is_empty()) {
+                write!(self.out, "{}", &line[written..span.start])?;
+                self.write_colored(|c| &c.matched, &&line[span.clone()])?;
+                written = span.end;
+            }
+        }
+        writeln!(self.out, "{}", &line[written..])
+    }
+
+    /// Writes the text, wrapped in the colour chosen by `pick` when colours
+    /// are enabled.
+    fn write_colored(
+        &mut self,
+        pick: fn(&Colors) -> &String,
+        text: &dyn Display,
+    ) -> io::Result<()> {
+        match &self.options.colors {
+            Some(colors) => write!(self.out, "{}", paint(pick(colors), &text.to_string())),
+            None => write!(self.out, "{}", text),
+        }
+    }
+
+    fn print_json(&mut self, event: serde_json::Value) -> io::Result<()> {
+        writeln!(self.out, "{}", event)
+    }
+}
+
+fn stats_json(stats: Stats) -> serde_json::Value {
+    json!({
+        "matched_lines": stats.matched_lines,
+        "matches": stats.matches,
+        "bytes_searched": stats.bytes_searched,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{search_reader, Matcher};
+
+    const POEM: &str = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.
+
+How dreary to be somebody!
+How public, like a frog
+To tell your name the livelong day
+To an admiring bog!";
+
+    fn render(with_path: bool, query: &str, before: usize, after: usize) -> String {
+        let options = PrintOptions {
+            before,
+            after,
+            with_path,
+            ..PrintOptions::default()
+        };
+        render_with(query, &options)
+    }
+
+    fn render_with(query: &str, options: &PrintOptions) -> String {
+        let mut printer = Printer::new(Vec::new(), options.clone());
+        let matcher = Matcher::literal(query, true);
+        let path = Path::new("poem.txt");
+        search_reader(&matcher, false, None, path, POEM.as_bytes(), &mut printer).unwrap();
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn without_context() {
+        assert_eq!(
+            "Then there's a pair of us - don't tell!\nTo tell your name the livelong day\n",
+            render(false, "tell", 0, 0)
+        );
+    }
+
+    #[test]
+    fn before_and_after_context() {
+        assert_eq!(
+            "poem.txt-How dreary to be somebody!\n\
+             poem.txt:How public, like a frog\n\
+             poem.txt-To tell your name the livelong day\n",
+            render(true, "frog", 1, 1)
+        );
+    }
+
+    #[test]
+    fn separated_groups() {
+        assert_eq!(
+            "poem.txt:Then there's a pair of us - don't tell!\n\
+             poem.txt-They'd banish us, you know.\n\
+             --\n\
+             poem.txt:To tell your name the livelong day\n\
+             poem.txt-To an admiring bog!\n",
+            render(true, "tell", 0, 1)
+        );
+    }
+
+    #[test]
+    fn merged_groups() {
+        assert_eq!(
+            "I'm nobody! Who are you?\n\
+             Are you nobody, too?\n\
+             Then there's a pair of us - don't tell!\n",
+            render(false, "nobody", 1, 1)
+        );
+    }
+
+    #[test]
+    fn separator_between_files() {
+        let options = PrintOptions {
+            after: 1,
+            with_path: true,
+            ..PrintOptions::default()
+        };
+        let mut printer = Printer::new(Vec::new(), options);
+        let matcher = Matcher::literal("frog", true);
+        for path in &["a.txt", "b.txt"] {
+            search_reader(
+                &matcher,
+                false,
+                None,
+                Path::new(path),
+                POEM.as_bytes(),
+                &mut printer,
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            "a.txt:How public, like a frog\n\
+             a.txt-To tell your name the livelong day\n\
+             --\n\
+             b.txt:How public, like a frog\n\
+             b.txt-To tell your name the livelong day\n",
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn line_numbers_columns_and_offsets() {
+        let options = PrintOptions {
+            with_path: true,
+            line_number: true,
+            column: true,
+            byte_offset: true,
+            ..PrintOptions::default()
+        };
+        assert_eq!(
+            "poem.txt:7:20:142:How public, like a frog\n",
+            render_with("frog", &options)
+        );
+    }
+
+    #[test]
+    fn context_lines_omit_column() {
+        let options = PrintOptions {
+            before: 1,
+            line_number: true,
+            column: true,
+            ..PrintOptions::default()
+        };
+        assert_eq!(
+            "6-How dreary to be somebody!\n7:20:How public, like a frog\n",
+            render_with("frog", &options)
+        );
+    }
+
+    #[test]
+    fn json_events() {
+        let options = PrintOptions {
+            after: 1,
+            json: true,
+            ..PrintOptions::default()
+        };
+        let events: Vec<serde_json::Value> = render_with("frog", &options)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(
+            vec![
+                json!({ "type": "begin", "data": { "path": "poem.txt" } }),
+                json!({
+                    "type": "match",
+                    "data": {
+                        "path": "poem.txt",
+                        "line_number": 7,
+                        "absolute_offset": 142,
+                        "text": "How public, like a frog",
+                        "submatches": [{ "match": "frog", "start": 19, "end": 23 }],
+                    },
+                }),
+                json!({
+                    "type": "context",
+                    "data": {
+                        "path": "poem.txt",
+                        "line_number": 8,
+                        "absolute_offset": 166,
+                        "text": "To tell your name the livelong day",
+                    },
+                }),
+                json!({
+                    "type": "end",
+                    "data": {
+                        "path": "poem.txt",
+                        "binary": false,
+                        "stats": { "matched_lines": 1, "matches": 1, "bytes_searched": 220 },
+                    },
+                }),
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn reports_patterns() {
+        let patterns = vec!["frog".to_string(), "bog".to_string(), "day".to_string()];
+        let options = PrintOptions {
+            line_number: true,
+            patterns: patterns.clone(),
+            ..PrintOptions::default()
+        };
+        let matcher = Matcher::literals(&patterns, true).unwrap();
+        let mut printer = Printer::new(Vec::new(), options);
+        let path = Path::new("poem.txt");
+        search_reader(&matcher, false, None, path, POEM.as_bytes(), &mut printer).unwrap();
+        assert_eq!(
+            "7:frog:How public, like a frog\n\
+             8:day:To tell your name the livelong day\n\
+             9:bog:To an admiring bog!\n",
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn ranks_by_distance() {
+        let options = PrintOptions {
+            line_number: true,
+            rank: true,
+            ..PrintOptions::default()
+        };
+        let matcher = Matcher::fuzzy("somebody", 3, true).unwrap();
+        let mut printer = Printer::new(Vec::new(), options);
+        let path = Path::new("poem.txt");
+        search_reader(&matcher, false, None, path, POEM.as_bytes(), &mut printer).unwrap();
+        printer.finish().unwrap();
+        assert_eq!(
+            "\
+6:~0:How dreary to be somebody!
+1:~3:I'm nobody! Who are you?
+2:~3:Are you nobody, too?
+",
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn replaced_lines_are_not_labelled() {
+        let options = PrintOptions {
+            line_number: true,
+            replaced: true,
+            rank: true,
+            patterns: vec!["somebody".to_string(), "nobody".to_string()],
+            ..PrintOptions::default()
+        };
+        let matcher = Matcher::fuzzy("somebody", 3, true).unwrap();
+        let mut printer = Printer::new(Vec::new(), options);
+        let path = Path::new("poem.txt");
+        search_reader(
+            &matcher,
+            false,
+            Some("X"),
+            path,
+            POEM.as_bytes(),
+            &mut printer,
+        )
+        .unwrap();
+        printer.finish().unwrap();
+        assert_eq!(
+            "\
+6:How dreary to be X!
+1:I'm X! Who are you?
+2:Are you X, too?
+",
+            String::from_utf8(printer.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn json_without_matches_is_silent() {
+        let options = PrintOptions {
+            json: true,
+            ..PrintOptions::default()
+        };
+        assert_eq!("", render_with("toad", &options));
+    }
+
+    #[test]
+    fn count_mode() {
+        let options = PrintOptions {
+            mode: OutputMode::Count,
+            with_path: true,
+            ..PrintOptions::default()
+        };
+        assert_eq!("poem.txt:2\n", render_with("tell", &options));
+        assert_eq!("poem.txt:0\n", render_with("toad", &options));
+    }
+
+    #[test]
+    fn file_list_modes() {
+        let options = PrintOptions {
+            mode: OutputMode::FilesWithMatches,
+            ..PrintOptions::default()
+        };
+        assert_eq!("poem.txt\n", render_with("tell", &options));
+        assert_eq!("", render_with("toad", &options));
+
+        let options = PrintOptions {
+            mode: OutputMode::FilesWithoutMatch,
+            ..PrintOptions::default()
+        };
+        assert_eq!("", render_with("tell", &options));
+        assert_eq!("poem.txt\n", render_with("toad", &options));
+    }
+
+    #[test]
+    fn quiet_mode() {
+        let options = PrintOptions {
+            mode: OutputMode::Quiet,
+            ..PrintOptions::default()
+        };
+        assert_eq!("", render_with("tell", &options));
+    }
+
+    #[test]
+    fn colored_line() {
+        let options = PrintOptions {
+            with_path: true,
+            line_number: true,
+            colors: Some(Colors::default()),
+            ..PrintOptions::default()
+        };
+        assert_eq!(
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m7\x1b[0m\x1b[36m:\x1b[0m\
+             How public, like a \x1b[01;31mfrog\x1b[0m\n",
+            render_with("frog", &options)
+        );
+    }
+
+    #[test]
+    fn colored_file_list() {
+        let options = PrintOptions {
+            mode: OutputMode::FilesWithMatches,
+            colors: Some(Colors::from_spec("fn=34")),
+            ..PrintOptions::default()
+        };
+        assert_eq!("\x1b[34mpoem.txt\x1b[0m\n", render_with("frog", &options));
+    }
+}
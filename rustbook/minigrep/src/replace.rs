@@ -0,0 +1,329 @@
+use crate::{is_binary, Matcher, Outcome};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Lines of context shown around each change in a unified diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// A single line changed by a replacement.
+#[derive(Debug, PartialEq)]
+pub struct LineEdit {
+    /// Index of the line within the original contents, starting at 0.
+    pub index: usize,
+    /// The replaced line, without its terminator. It may span several lines
+    /// when the template contains newlines.
+    pub new: String,
+}
+
+/// A line of the original contents split from its terminator, which is
+/// `\n`, `\r\n` or nothing for a final unterminated line.
+struct Line<'a> {
+    text: &'a str,
+    terminator: &'a str,
+}
+
+fn split_lines(contents: &str) -> Vec<Line<'_>> {
+    contents
+        .split_inclusive('\n')
+        .map(|raw| {
+            let text = match raw.strip_suffix('\n') {
+                Some(text) => text.strip_suffix('\r').unwrap_or(text),
+                None => raw,
+            };
+            Line {
+                text,
+                terminator: &raw[text.len()..],
+            }
+        })
+        .collect()
+}
+
+/// Finds every line the replacement would change.
+pub fn line_edits(matcher: &Matcher, template: &str, contents: &str) -> Vec<LineEdit> {
+    split_lines(contents)
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let (new, spans) = matcher.replace(line.text, template);
+            if spans.is_empty() || new == line.text {
+                return None;
+            }
+            Some(LineEdit { index, new })
+        })
+        .collect()
+}
+
+/// Applies the edits to the contents, keeping each line's terminator.
+pub fn apply_edits(contents: &str, edits: &[LineEdit]) -> String {
+    let mut out = String::with_capacity(contents.len());
+    let mut edits = edits.iter().peekable();
+    for (index, line) in split_lines(contents).iter().enumerate() {
+        match edits.peek() {
+            Some(edit) if edit.index == index => {
+                out.push_str(&edit.new);
+                edits.next();
+            }
+            _ => out.push_str(line.text),
+        }
+        out.push_str(line.terminator);
+    }
+    out
+}
+
+/// Renders the edits as a unified diff between `a/path` and `b/path`.
+pub fn unified_diff(path: &Path, contents: &str, edits: &[LineEdit]) -> String {
+    let lines = split_lines(contents);
+    let mut out = format!("--- a/{}\n+++ b/{}\n", path.display(), path.display());
+
+    // group edits whose context windows overlap or touch into hunks
+    let mut hunks: Vec<(usize, usize, Vec<&LineEdit>)> = Vec::new();
+    for edit in edits {
+        let start = edit.index.saturating_sub(DIFF_CONTEXT);
+        let end = (edit.index + DIFF_CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some((_, last_end, group)) if start <= *last_end => {
+                *last_end = end;
+                group.push(edit);
+            }
+            _ => hunks.push((start, end, vec![edit])),
+        }
+    }
+
+    // each edit can change the number of lines, shifting later hunks
+    let mut shift: isize = 0;
+    for (start, end, group) in hunks {
+        let added: usize = group.iter().map(|edit| edit.new.split('\n').count()).sum();
+        let old_len = end - start;
+        let new_len = old_len - group.len() + added;
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            start + 1,
+            old_len,
+            (start as isize + shift) as usize + 1,
+            new_len
+        ));
+        shift += new_len as isize - old_len as isize;
+
+        // runs of adjacent changed lines show all removals before additions,
+        // as diff(1) does
+        let mut added = Vec::new();
+        let mut group = group.into_iter().peekable();
+        for (index, line) in lines.iter().enumerate().take(end).skip(start) {
+            let missing_newline = line.terminator.is_empty();
+            match group.peek() {
+                Some(edit) if edit.index == index => {
+                    push_diff_line(&mut out, '-', line.text, missing_newline);
+                    added.push((edit.new.as_str(), missing_newline));
+                    group.next();
+                }
+                _ => {
+                    flush_added(&mut out, &mut added);
+                    push_diff_line(&mut out, ' ', line.text, missing_newline);
+                }
+            }
+        }
+        flush_added(&mut out, &mut added);
+    }
+    out
+}
+
+fn flush_added(out: &mut String, added: &mut Vec<(&str, bool)>) {
+    for (text, missing_newline) in added.drain(..) {
+        push_diff_line(out, '+', text, missing_newline);
+    }
+}
+
+fn push_diff_line(out: &mut String, marker: char, text: &str, missing_newline: bool) {
+    for line in text.split('\n') {
+        out.push(marker);
+        out.push_str(line);
+        out.push('\n');
+    }
+    if missing_newline {
+        out.push_str("\\ No newline at end of file\n");
+    }
+}
+
+/// Applies the replacement to each file, either rewriting it in place or,
+/// with `dry_run`, writing a unified diff of the changes to `out`. Binary
+/// files and files that aren't valid UTF-8 are left alone, as are files the
+/// replacement doesn't change. A file that can't be read or written is
+/// reported and skipped.
+pub fn rewrite_files<W: Write>(
+    files: &[PathBuf],
+    matcher: &Matcher,
+    template: &str,
+    dry_run: bool,
+    mut out: W,
+) -> io::Result<Outcome> {
+    let mut outcome = Outcome::default();
+    for path in files {
+        let result = fs::read(path).and_then(|bytes| {
+            let contents = match String::from_utf8(bytes) {
+                Ok(v) if !is_binary(v.as_bytes()) => v,
+                _ => return Ok(false),
+            };
+            let edits = line_edits(matcher, template, &contents);
+            if edits.is_empty() {
+                return Ok(false);
+            }
+            if dry_run {
+                out.write_all(unified_diff(path, &contents, &edits).as_bytes())?;
+            } else {
+                write_atomic(path, apply_edits(&contents, &edits).as_bytes())?;
+            }
+            Ok(true)
+        });
+        match result {
+            Ok(changed) => outcome.matched |= changed,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                outcome.errors = true;
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+/// Atomically replaces the file's contents by writing them to a temporary
+/// file in the same directory and renaming it over the original. The
+/// original file's permissions are kept. A symlink is followed, so that the
+/// file it points to is replaced rather than the link itself. A file that
+/// doesn't exist yet is created.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let (path, permissions) = match fs::canonicalize(path) {
+        Ok(path) => {
+            let permissions = fs::metadata(&path)?.permissions();
+            (path, Some(permissions))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (path.to_path_buf(), None),
+        Err(e) => return Err(e),
+    };
+    let dir = match path.parent() {
+        Some(v) if !v.as_os_str().is_empty() => v,
+        _ => Path::new("."),
+    };
+
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    temp.write_all(contents)?;
+    temp.as_file().sync_all()?;
+    if let Some(permissions) = permissions {
+        fs::set_permissions(temp.path(), permissions)?;
+    }
+    temp.persist(&path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENTS: &str = "\
+one
+two
+three
+four
+five
+six
+seven
+eight
+nine
+ten";
+
+    #[test]
+    fn apply_keeps_terminators() {
+        let matcher = Matcher::literal("o", true);
+        let contents = "foo\r\nbar\nboo";
+        let edits = line_edits(&matcher, "0", contents);
+        assert_eq!(
+            vec![0, 2],
+            edits.iter().map(|e| e.index).collect::<Vec<_>>()
+        );
+        assert_eq!("f00\r\nbar\nb00", apply_edits(contents, &edits));
+    }
+
+    #[test]
+    fn diff_single_hunk() {
+        let matcher = Matcher::regex("^(t)(wo|hree)$", true).unwrap();
+        let edits = line_edits(&matcher, "${1}_$2", CONTENTS);
+        assert_eq!(
+            "--- a/n.txt\n+++ b/n.txt\n@@ -1,6 +1,6 @@\n one\n-two\n-three\n+t_wo\n+t_hree\n four\n five\n six\n",
+            unified_diff(Path::new("n.txt"), CONTENTS, &edits)
+        );
+    }
+
+    #[test]
+    fn diff_separate_hunks_with_shift() {
+        let matcher = Matcher::regex("^(one|ten)$", true).unwrap();
+        let edits = line_edits(&matcher, "$1\n$1", CONTENTS);
+        assert_eq!(
+            "--- a/n.txt\n+++ b/n.txt\n\
+             @@ -1,4 +1,5 @@\n-one\n+one\n+one\n two\n three\n four\n\
+             @@ -7,4 +8,5 @@\n seven\n eight\n nine\n-ten\n\\ No newline at end of file\n+ten\n+ten\n\\ No newline at end of file\n",
+            unified_diff(Path::new("n.txt"), CONTENTS, &edits)
+        );
+    }
+
+    #[test]
+    fn rewrite_and_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let changed = dir.path().join("a.txt");
+        let unchanged = dir.path().join("b.txt");
+        fs::write(&changed, "safe, fast, productive.\n").unwrap();
+        fs::write(&unchanged, "Pick three.\n").unwrap();
+        let files = vec![changed.clone(), unchanged.clone()];
+        let matcher = Matcher::literal("fast", true);
+
+        let mut diff = Vec::new();
+        let outcome = rewrite_files(&files, &matcher, "quick", true, &mut diff).unwrap();
+        assert!(outcome.matched);
+        assert!(String::from_utf8(diff)
+            .unwrap()
+            .ends_with("@@ -1,1 +1,1 @@\n-safe, fast, productive.\n+safe, quick, productive.\n"));
+        assert_eq!(
+            "safe, fast, productive.\n",
+            fs::read_to_string(&changed).unwrap()
+        );
+
+        let outcome = rewrite_files(&files, &matcher, "quick", false, io::sink()).unwrap();
+        assert!(outcome.matched);
+        assert_eq!(
+            "safe, quick, productive.\n",
+            fs::read_to_string(&changed).unwrap()
+        );
+        assert_eq!("Pick three.\n", fs::read_to_string(&unchanged).unwrap());
+    }
+
+    #[test]
+    fn write_atomic_replaces_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("poem.txt");
+        fs::write(&path, "Rust:\nsafe").unwrap();
+        write_atomic(&path, b"Rust:\nfast").unwrap();
+        assert_eq!("Rust:\nfast", fs::read_to_string(&path).unwrap());
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic_follows_symlinks_and_keeps_permissions() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("poem.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&target, "Rust:\nsafe").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o640)).unwrap();
+        symlink(&target, &link).unwrap();
+
+        write_atomic(&link, b"Rust:\nfast").unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!("Rust:\nfast", fs::read_to_string(&target).unwrap());
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(0o640, mode & 0o777);
+    }
+}
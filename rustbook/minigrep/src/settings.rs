@@ -0,0 +1,338 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Name of the per-project configuration file, looked for in the current
+/// directory and each of its parents.
+pub const PROJECT_FILE: &str = ".minigrep.toml";
+
+/// Where the value of an option came from, as reported by `--debug-config`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    CommandLine,
+    /// An environment variable, named here.
+    Environment(&'static str),
+    /// A configuration file, either its defaults or one of its profiles.
+    File {
+        path: PathBuf,
+        profile: Option<String>,
+    },
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::CommandLine => write!(f, "command line"),
+            Source::Environment(name) => write!(f, "{} environment variable", name),
+            Source::File {
+                path,
+                profile: None,
+            } => write!(f, "{}", path.display()),
+            Source::File {
+                path,
+                profile: Some(profile),
+            } => write!(f, "{} [profile.{}]", path.display(), profile),
+        }
+    }
+}
+
+/// The value given to an option in a configuration file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingValue {
+    /// `true` turns a flag on, and `false` undoes an earlier file turning
+    /// it on.
+    Flag(bool),
+    Text(String),
+    /// Values for an option that may be given several times.
+    List(Vec<String>),
+}
+
+/// An option set in a configuration file, named by its long form without
+/// the leading dashes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting {
+    pub key: String,
+    pub value: SettingValue,
+    pub source: Source,
+}
+
+/// Default options and named profiles read from the configuration files.
+/// Top-level keys apply to every search, while `[profile.NAME]` tables only
+/// apply when selected with `--profile NAME`:
+///
+/// ```toml
+/// smart-case = true
+///
+/// [profile.rust]
+/// include = ["*.rs"]
+/// exclude = ["target"]
+/// ```
+///
+/// Files read later take precedence, so a project's settings override the
+/// user's. A file that can't be read or parsed is only reported when its
+/// settings are used, so that `--no-config` still works around it.
+#[derive(Debug, Default)]
+pub struct Settings {
+    /// The files read, in order.
+    pub files: Vec<PathBuf>,
+    defaults: Vec<Setting>,
+    /// Settings of each profile, in the order the files were read. A
+    /// profile defined in several files appears once per file.
+    profiles: Vec<(String, Setting)>,
+    errors: Vec<String>,
+}
+
+impl Settings {
+    /// Reads the user's configuration, `~/.config/minigrep/config.toml` or
+    /// under `$XDG_CONFIG_HOME`, then the nearest `.minigrep.toml` at or
+    /// above the current directory. Missing files are skipped.
+    pub fn load() -> Settings {
+        let mut settings = Settings::default();
+        if let Some(path) = user_file() {
+            settings.read(&path);
+        }
+        if let Some(path) = env::current_dir().ok().and_then(|dir| project_file(&dir)) {
+            settings.read(&path);
+        }
+        settings
+    }
+
+    /// Adds the settings of a file if it exists.
+    pub fn read(&mut self, path: &Path) {
+        match fs::read_to_string(path) {
+            Ok(text) => self.parse(path, &text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => self.errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    /// Adds the settings held in the text of a file.
+    pub fn parse(&mut self, path: &Path, text: &str) {
+        self.files.push(path.to_path_buf());
+        if let Err(e) = self.parse_table(path, text) {
+            self.errors.push(format!("{}: {}", path.display(), e));
+        }
+    }
+
+    fn parse_table(&mut self, path: &Path, text: &str) -> Result<(), String> {
+        let table: Table = text.parse().map_err(|e: toml::de::Error| match e.span() {
+            Some(span) => {
+                let line = text[..span.start].matches('\n').count() + 1;
+                format!("line {}: {}", line, e.message())
+            }
+            None => e.message().to_string(),
+        })?;
+        for (key, value) in table {
+            if key != "profile" {
+                let source = Source::File {
+                    path: path.to_path_buf(),
+                    profile: None,
+                };
+                self.defaults.push(setting(key, value, source)?);
+                continue;
+            }
+            let profiles = match value {
+                Value::Table(v) => v,
+                _ => return Err("'profile' must be a table of profiles".to_string()),
+            };
+            for (name, profile) in profiles {
+                let profile = match profile {
+                    Value::Table(v) => v,
+                    _ => return Err(format!("profile '{}' must be a table", name)),
+                };
+                for (key, value) in profile {
+                    let source = Source::File {
+                        path: path.to_path_buf(),
+                        profile: Some(name.clone()),
+                    };
+                    self.profiles
+                        .push((name.clone(), setting(key, value, source)?));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The first problem found reading the files, if any.
+    pub fn error(&self) -> Option<&str> {
+        self.errors.first().map(String::as_str)
+    }
+
+    /// The settings that apply to every search.
+    pub fn defaults(&self) -> &[Setting] {
+        &self.defaults
+    }
+
+    /// The settings of the named profile, or `None` when no file defines it.
+    pub fn profile(&self, name: &str) -> Option<Vec<&Setting>> {
+        let settings: Vec<&Setting> = self
+            .profiles
+            .iter()
+            .filter(|(profile, _)| profile == name)
+            .map(|(_, setting)| setting)
+            .collect();
+        match settings.is_empty() {
+            true => None,
+            false => Some(settings),
+        }
+    }
+}
+
+fn setting(key: String, value: Value, source: Source) -> Result<Setting, String> {
+    let value = match value {
+        Value::Boolean(b) => SettingValue::Flag(b),
+        Value::Array(values) => {
+            let values = values.into_iter().map(text).collect::<Option<_>>();
+            SettingValue::List(values.ok_or(format!("'{}' must be a list of strings", key))?)
+        }
+        value => {
+            let value = text(value);
+            let kinds = "a boolean, a string, a number or a list";
+            SettingValue::Text(value.ok_or(format!("'{}' must be {}", key, kinds))?)
+        }
+    };
+    Ok(Setting { key, value, source })
+}
+
+/// Strings are taken as they are and non-negative integers written out.
+fn text(value: Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s),
+        Value::Integer(n) if n >= 0 => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// `$XDG_CONFIG_HOME/minigrep/config.toml`, defaulting to `~/.config`.
+fn user_file() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("minigrep").join("config.toml"))
+}
+
+/// The nearest `.minigrep.toml` in the directory or one of its parents.
+pub fn project_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, profile: Option<&str>) -> Source {
+        Source::File {
+            path: PathBuf::from(path),
+            profile: profile.map(String::from),
+        }
+    }
+
+    #[test]
+    fn defaults_and_profiles() {
+        let mut settings = Settings::default();
+        settings.parse(
+            Path::new("user.toml"),
+            "smart-case = true\ncontext = 2\n\n\
+             [profile.rust]\ninclude = [\"*.rs\"]\nexclude = \"target\"\n",
+        );
+        settings.parse(
+            Path::new(".minigrep.toml"),
+            "color = \"always\"\n[profile.rust]\nsmart-case = false\n",
+        );
+        assert_eq!(None, settings.error());
+        assert_eq!(
+            vec![PathBuf::from("user.toml"), PathBuf::from(".minigrep.toml")],
+            settings.files
+        );
+
+        let keys: Vec<(&str, &SettingValue)> = settings
+            .defaults()
+            .iter()
+            .map(|s| (s.key.as_str(), &s.value))
+            .collect();
+        assert_eq!(
+            vec![
+                ("smart-case", &SettingValue::Flag(true)),
+                ("context", &SettingValue::Text("2".to_string())),
+                ("color", &SettingValue::Text("always".to_string())),
+            ],
+            keys
+        );
+
+        let rust = settings.profile("rust").unwrap();
+        assert_eq!(3, rust.len());
+        assert_eq!(SettingValue::List(vec!["*.rs".to_string()]), rust[0].value);
+        assert_eq!(file("user.toml", Some("rust")), rust[0].source);
+        assert_eq!(file(".minigrep.toml", Some("rust")), rust[2].source);
+        assert!(settings.profile("python").is_none());
+    }
+
+    #[test]
+    fn invalid_files() {
+        let error = |text: &str| {
+            let mut settings = Settings::default();
+            settings.parse(Path::new("bad.toml"), text);
+            settings.error().map(String::from)
+        };
+        assert!(error("a = 1\ncontext = ")
+            .unwrap()
+            .starts_with("bad.toml: line 2: "));
+        assert_eq!(
+            Some("bad.toml: 'context' must be a boolean, a string, a number or a list"),
+            error("context = 1.5").as_deref()
+        );
+        assert_eq!(
+            Some("bad.toml: 'include' must be a list of strings"),
+            error("include = [true]").as_deref()
+        );
+        assert_eq!(
+            Some("bad.toml: profile 'rust' must be a table"),
+            error("profile.rust = 1").as_deref()
+        );
+    }
+
+    #[test]
+    fn missing_files_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut settings = Settings::default();
+        settings.read(&dir.path().join("config.toml"));
+        assert!(settings.files.is_empty());
+        assert_eq!(None, settings.error());
+    }
+
+    #[test]
+    fn nearest_project_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(None, project_file(&nested));
+
+        fs::write(dir.path().join(PROJECT_FILE), "").unwrap();
+        assert_eq!(Some(dir.path().join(PROJECT_FILE)), project_file(&nested));
+        fs::write(dir.path().join("a").join(PROJECT_FILE), "").unwrap();
+        assert_eq!(
+            Some(dir.path().join("a").join(PROJECT_FILE)),
+            project_file(&nested)
+        );
+    }
+
+    #[test]
+    fn sources() {
+        assert_eq!("command line", Source::CommandLine.to_string());
+        assert_eq!(
+            "CASE_INSENSITIVE environment variable",
+            Source::Environment("CASE_INSENSITIVE").to_string()
+        );
+        assert_eq!("a.toml", file("a.toml", None).to_string());
+        assert_eq!(
+            "a.toml [profile.rust]",
+            file("a.toml", Some("rust")).to_string()
+        );
+    }
+}
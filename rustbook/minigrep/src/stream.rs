@@ -0,0 +1,463 @@
+use crate::{Finder, Match, Matcher, Printer};
+use memchr::{memchr, memchr_iter, memrchr};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+
+/// Longest line searched. Any more of a line is skipped up to its newline, so
+/// that an input without newlines can't exhaust memory.
+pub const MAX_LINE: usize = 16 * 1024 * 1024;
+
+/// Counters gathered while searching a single input.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub matched_lines: usize,
+    pub matches: usize,
+    pub bytes_searched: usize,
+}
+
+/// A context line waiting to be printed in case a match follows it.
+struct Pending {
+    line_number: usize,
+    byte_offset: usize,
+    line: String,
+}
+
+/// Where a reader starts within its input: the number of lines before it and
+/// their length in bytes. Used when searching only the tail of a file.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub lines: usize,
+    pub offset: usize,
+}
+
+/// Searches the reader one line at a time and hands matches and context lines
+/// to the printer as they are found. Only the current line and the requested
+/// before-context are held in memory, so inputs larger than RAM and
+/// unbounded pipes can be searched.
+///
+/// With `invert`, the lines that do not match are selected instead, and are
+/// reported as matches without any submatches.
+///
+/// With `replace`, selected lines are printed with every match substituted by
+/// the template, and submatches refer to the substituted text.
+///
+/// Lines that are not valid UTF-8 are decoded lossily before matching. Line
+/// spans and byte offsets refer to the raw input, while submatch spans refer
+/// to the decoded line.
+///
+/// Only the first `MAX_LINE` bytes of a line are searched.
+pub fn search_reader<R: BufRead, W: Write>(
+    matcher: &Matcher,
+    invert: bool,
+    replace: Option<&str>,
+    path: &Path,
+    reader: R,
+    printer: &mut Printer<W>,
+) -> io::Result<Stats> {
+    let start = Position::default();
+    search_reader_at(matcher, invert, replace, path, reader, printer, start)
+}
+
+/// Like `search_reader`, for a reader that starts part way through its input.
+/// Line numbers and byte offsets continue from `start`.
+///
+/// Case sensitive literal queries are searched for a buffered block at a
+/// time with `Finder`, and line boundaries are only looked for around each
+/// hit and the context lines that may be printed.
+pub fn search_reader_at<R: BufRead, W: Write>(
+    matcher: &Matcher,
+    invert: bool,
+    replace: Option<&str>,
+    path: &Path,
+    mut reader: R,
+    printer: &mut Printer<W>,
+    start: Position,
+) -> io::Result<Stats> {
+    let finder = matcher.finder().filter(|_| !invert);
+    let mut search = Search {
+        matcher,
+        invert,
+        replace,
+        path,
+        before: printer.options().before,
+        first_only: printer.options().mode.stops_at_first_match(),
+        printer,
+        start,
+        stats: Stats::default(),
+        line_number: start.lines,
+        history: VecDeque::new(),
+        after_left: 0,
+    };
+
+    search.printer.begin_file(path)?;
+    let mut buf = Vec::new();
+    loop {
+        if let Some(finder) = &finder {
+            let block = reader.fill_buf()?;
+            // only whole lines are scanned; a line running past the end of
+            // the buffer is read on its own below
+            if let Some(end) = memrchr(b'\n', block).map(|i| i + 1) {
+                let stop = search.block(finder, &block[..end])?;
+                reader.consume(end);
+                if stop {
+                    break;
+                }
+                continue;
+            }
+        }
+        buf.clear();
+        let n = read_line(&mut reader, &mut buf, MAX_LINE)?;
+        if n == 0 || search.line(&buf, n)? {
+            break;
+        }
+    }
+    let stats = search.stats;
+    search.printer.end_file(path, stats)?;
+    Ok(stats)
+}
+
+/// The state of a search through one input.
+struct Search<'a, W: Write> {
+    matcher: &'a Matcher,
+    invert: bool,
+    replace: Option<&'a str>,
+    path: &'a Path,
+    printer: &'a mut Printer<W>,
+    before: usize,
+    first_only: bool,
+    start: Position,
+    stats: Stats,
+    line_number: usize,
+    /// The lines that may still be printed as before-context.
+    history: VecDeque<Pending>,
+    /// How many more lines are printed as after-context.
+    after_left: usize,
+}
+
+impl<W: Write> Search<'_, W> {
+    /// Searches a line, of which `consumed` bytes were read from the input.
+    /// Returns true once the search can stop.
+    fn line(&mut self, buf: &[u8], consumed: usize) -> io::Result<bool> {
+        self.line_number += 1;
+        let byte_offset = self.start.offset + self.stats.bytes_searched;
+        self.stats.bytes_searched += consumed;
+
+        let raw = trim_terminator(buf);
+        let line = String::from_utf8_lossy(raw);
+        let (found, distance) = self.matcher.find_scored(&line);
+        let (submatches, patterns): (Vec<_>, Vec<_>) = found.into_iter().unzip();
+
+        if submatches.is_empty() != self.invert {
+            if self.after_left > 0 {
+                self.printer
+                    .print_context(self.path, self.line_number, byte_offset, &line)?;
+                self.after_left -= 1;
+            } else if self.before > 0 {
+                if self.history.len() == self.before {
+                    self.history.pop_front();
+                }
+                self.history.push_back(Pending {
+                    line_number: self.line_number,
+                    byte_offset,
+                    line: line.into_owned(),
+                });
+            }
+            return Ok(false);
+        }
+
+        for p in self.history.drain(..) {
+            self.printer
+                .print_context(self.path, p.line_number, p.byte_offset, &p.line)?;
+        }
+        self.stats.matched_lines += 1;
+        self.stats.matches += submatches.len();
+        let (text, submatches) = match self.replace {
+            Some(template) if !self.invert => {
+                let (text, spans) = self.matcher.replace(&line, template);
+                (Cow::Owned(text), spans)
+            }
+            _ => (line, submatches),
+        };
+        let m = Match {
+            line_number: self.line_number,
+            span: byte_offset..byte_offset + raw.len(),
+            submatches,
+            patterns,
+            distance,
+            line: &text,
+        };
+        self.printer.print_match(self.path, &m)?;
+        self.after_left = self.printer.options().after;
+        Ok(self.first_only)
+    }
+
+    /// Searches a block of whole lines for the finder's needle, handing
+    /// only the lines holding it to `line`. Returns true once the search
+    /// can stop.
+    fn block(&mut self, finder: &Finder, block: &[u8]) -> io::Result<bool> {
+        let mut at = 0;
+        while let Some(hit) = finder.find(block, at) {
+            let start = memrchr(b'\n', &block[at..hit]).map_or(at, |i| at + i + 1);
+            let end = hit + memchr(b'\n', &block[hit..]).map_or(block.len() - hit, |i| i + 1);
+            self.skip(&block[at..start])?;
+            if self.line(&block[start..end], end - start)? {
+                return Ok(true);
+            }
+            at = end;
+        }
+        self.skip(&block[at..])?;
+        Ok(false)
+    }
+
+    /// Passes over whole lines known not to match, only splitting those that
+    /// may be printed as context into lines.
+    fn skip(&mut self, mut lines: &[u8]) -> io::Result<()> {
+        while self.after_left > 0 && !lines.is_empty() {
+            let end = memchr(b'\n', lines).map_or(lines.len(), |i| i + 1);
+            self.line(&lines[..end], end)?;
+            lines = &lines[end..];
+        }
+        let mut kept = lines.len();
+        for _ in 0..self.before {
+            if kept == 0 {
+                break;
+            }
+            kept = memrchr(b'\n', &lines[..kept - 1]).map_or(0, |i| i + 1);
+        }
+        let (skipped, kept) = lines.split_at(kept);
+        self.line_number += memchr_iter(b'\n', skipped).count();
+        self.stats.bytes_searched += skipped.len();
+        for line in kept.split_inclusive(|&b| b == b'\n') {
+            self.line(line, line.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns true as soon as any line of the reader is selected, without
+/// printing. With `invert`, a line is selected when it does not match.
+pub fn reader_matches<R: BufRead>(
+    matcher: &Matcher,
+    invert: bool,
+    mut reader: R,
+) -> io::Result<bool> {
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if read_line(&mut reader, &mut buf, MAX_LINE)? == 0 {
+            return Ok(false);
+        }
+        if matcher.is_match(&String::from_utf8_lossy(trim_terminator(&buf))) != invert {
+            return Ok(true);
+        }
+    }
+}
+
+/// Reads a line into `buf` like `read_until`, keeping at most `max` bytes of
+/// it and skipping the rest. Returns the number of bytes consumed, including
+/// those skipped.
+fn read_line<R: BufRead>(reader: &mut R, buf: &mut Vec<u8>, max: usize) -> io::Result<usize> {
+    let mut n = reader.by_ref().take(max as u64).read_until(b'\n', buf)?;
+    if n < max || buf.ends_with(b"\n") {
+        return Ok(n);
+    }
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let (skip, done) = match memchr(b'\n', available) {
+            Some(i) => (i + 1, true),
+            None => (available.len(), available.is_empty()),
+        };
+        reader.consume(skip);
+        n += skip;
+        if done {
+            return Ok(n);
+        }
+    }
+}
+
+/// Strips a trailing `\n` or `\r\n`, matching the behaviour of `str::lines`.
+fn trim_terminator(buf: &[u8]) -> &[u8] {
+    match buf.strip_suffix(b"\n") {
+        Some(line) => line.strip_suffix(b"\r").unwrap_or(line),
+        None => buf,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrintOptions;
+    use std::io::{BufReader, Read};
+
+    fn run(query: &str, input: &[u8]) -> (String, Stats) {
+        run_with(query, false, input)
+    }
+
+    fn run_with(query: &str, invert: bool, input: &[u8]) -> (String, Stats) {
+        let options = PrintOptions {
+            line_number: true,
+            ..PrintOptions::default()
+        };
+        let mut printer = Printer::new(Vec::new(), options);
+        let matcher = Matcher::literal(query, true);
+        let stats =
+            search_reader(&matcher, invert, None, Path::new("-"), input, &mut printer).unwrap();
+        (String::from_utf8(printer.into_inner()).unwrap(), stats)
+    }
+
+    #[test]
+    fn streams_lines() {
+        let (out, stats) = run("duct", b"Rust:\r\nsafe, fast, productive.\nPick three.");
+        assert_eq!("2:safe, fast, productive.\n", out);
+        assert_eq!(
+            Stats {
+                matched_lines: 1,
+                matches: 1,
+                bytes_searched: 42
+            },
+            stats
+        );
+    }
+
+    #[test]
+    fn lone_carriage_return_is_kept() {
+        assert_eq!(b"abc", trim_terminator(b"abc\r\n"));
+        assert_eq!(b"abc\r", trim_terminator(b"abc\r"));
+        let (out, _) = run("c\r", b"abc\r");
+        assert_eq!("1:abc\r\n", out);
+    }
+
+    #[test]
+    fn invert() {
+        let (out, stats) = run_with("duct", true, b"Rust:\nsafe, fast, productive.\nPick three.");
+        assert_eq!("1:Rust:\n3:Pick three.\n", out);
+        assert_eq!(2, stats.matched_lines);
+        assert_eq!(0, stats.matches);
+    }
+
+    #[test]
+    fn invalid_utf8_is_decoded_lossily() {
+        let (out, _) = run("fast", b"safe, \xff fast\n\xfe\xfe\n");
+        assert_eq!("1:safe, \u{fffd} fast\n", out);
+    }
+
+    #[test]
+    fn replace_selected_lines() {
+        let options = PrintOptions {
+            before: 1,
+            ..PrintOptions::default()
+        };
+        let mut printer = Printer::new(Vec::new(), options);
+        let matcher = Matcher::regex(r"(\w+)uctive", true).unwrap();
+        let input = b"Rust:\nsafe, fast, productive.\nPick three.";
+        let stats = search_reader(
+            &matcher,
+            false,
+            Some("${1}uction"),
+            Path::new("-"),
+            &input[..],
+            &mut printer,
+        )
+        .unwrap();
+        let out = String::from_utf8(printer.into_inner()).unwrap();
+        assert_eq!("Rust:\nsafe, fast, production.\n", out);
+        assert_eq!(1, stats.matches);
+    }
+
+    #[test]
+    fn long_lines_are_cut() {
+        let mut reader = &b"abcdefgh\nxyz\nlonger than four"[..];
+        let mut buf = Vec::new();
+        assert_eq!(9, read_line(&mut reader, &mut buf, 4).unwrap());
+        assert_eq!(b"abcd", &buf[..]);
+        buf.clear();
+        assert_eq!(4, read_line(&mut reader, &mut buf, 4).unwrap());
+        assert_eq!(b"xyz\n", &buf[..]);
+        buf.clear();
+        assert_eq!(16, read_line(&mut reader, &mut buf, 4).unwrap());
+        assert_eq!(b"long", &buf[..]);
+        buf.clear();
+        assert_eq!(0, read_line(&mut reader, &mut buf, 4).unwrap());
+    }
+
+    #[test]
+    fn block_search_agrees_with_line_search() {
+        let input: String = (0..300)
+            .map(|n| match n % 13 {
+                0 | 4 => format!("a needle here and a needle there {}\n", n),
+                7 => format!("{}\r\n", "hay ".repeat(n % 40)),
+                _ => format!("just hay {}\n", n),
+            })
+            .chain(std::iter::once("needle without newline".to_string()))
+            .collect();
+        let literal = Matcher::literal("needle", true);
+        let regex = Matcher::regex("needle", true).unwrap();
+        assert!(literal.finder().is_some() && regex.finder().is_none());
+
+        for (before, after) in [(0, 0), (1, 0), (0, 2), (3, 1), (20, 20)] {
+            for &capacity in &[16, 100, 8192] {
+                let run = |matcher: &Matcher| {
+                    let options = PrintOptions {
+                        before,
+                        after,
+                        line_number: true,
+                        byte_offset: true,
+                        ..PrintOptions::default()
+                    };
+                    let mut printer = Printer::new(Vec::new(), options);
+                    let reader = BufReader::with_capacity(capacity, input.as_bytes());
+                    let stats =
+                        search_reader(matcher, false, None, Path::new("-"), reader, &mut printer)
+                            .unwrap();
+                    (String::from_utf8(printer.into_inner()).unwrap(), stats)
+                };
+                assert_eq!(
+                    run(&regex),
+                    run(&literal),
+                    "-B{} -A{} with {}",
+                    before,
+                    after,
+                    capacity
+                );
+            }
+        }
+    }
+
+    /// Produces an endless-looking stream without holding it in memory.
+    struct Repeat {
+        remaining: usize,
+    }
+
+    impl Read for Repeat {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let line = b"nothing to see here\n";
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            self.remaining -= 1;
+            let n = line.len().min(buf.len());
+            buf[..n].copy_from_slice(&line[..n]);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn reader_matches_stops_early() {
+        let matcher = Matcher::literal("see", true);
+        let mut reader = BufReader::new(Repeat {
+            remaining: 1_000_000,
+        });
+        assert!(reader_matches(&matcher, false, &mut reader).unwrap());
+        assert!(reader.into_inner().remaining > 0);
+
+        let matcher = Matcher::literal("missing", true);
+        let reader = BufReader::new(Repeat { remaining: 1000 });
+        assert!(!reader_matches(&matcher, false, reader).unwrap());
+    }
+}
@@ -0,0 +1,496 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Include and exclude globs applied while walking a directory. Globs are
+/// tested against both the file name and the path relative to the search
+/// root, so `*.rs` and `target/**` both behave as expected.
+pub struct GlobFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl GlobFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<GlobFilter, globset::Error> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_set(include)?)
+        };
+        let exclude = build_set(exclude)?;
+        Ok(GlobFilter { include, exclude })
+    }
+
+    /// Returns true when a file should be searched. Files must match one of
+    /// the include globs (if any were given) and none of the exclude globs.
+    pub fn is_file_selected(&self, rel: &Path) -> bool {
+        let included = match &self.include {
+            Some(set) => matches(set, rel),
+            None => true,
+        };
+        included && !matches(&self.exclude, rel)
+    }
+
+    /// Returns true when a directory should be descended into. A directory is
+    /// pruned when an exclude glob matches it directly (`target`) or matches
+    /// everything beneath it (`target/**`).
+    pub fn is_dir_selected(&self, rel: &Path) -> bool {
+        !matches(&self.exclude, rel) && !self.exclude.is_match(rel.join(""))
+    }
+}
+
+fn build_set(globs: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob)?);
+    }
+    builder.build()
+}
+
+fn matches(set: &GlobSet, rel: &Path) -> bool {
+    set.is_match(rel) || rel.file_name().is_some_and(|name| set.is_match(name))
+}
+
+/// Controls which entries are skipped while walking, independent of the
+/// include/exclude globs.
+#[derive(Debug, Clone, Copy)]
+pub struct WalkOptions {
+    /// Search hidden files and directories (names starting with `.`).
+    pub hidden: bool,
+    /// Honour `.ignore` rules and, inside a git repository, `.gitignore`
+    /// and `.git/info/exclude` rules.
+    pub ignore: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> WalkOptions {
+        WalkOptions {
+            hidden: false,
+            ignore: true,
+        }
+    }
+}
+
+/// Tracks the state of a single walk. Ignore rules are kept as a stack with
+/// one entry per directory that has ignore files, innermost last.
+struct Walker<'a> {
+    root: &'a Path,
+    abs_root: PathBuf,
+    filter: &'a GlobFilter,
+    options: WalkOptions,
+    ignores: Vec<Gitignore>,
+    files: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
+    /// Whether anything beneath the root couldn't be read.
+    errors: bool,
+}
+
+/// Recursively collects the files beneath `root` that pass the filter.
+/// Entries are visited in sorted order so output is deterministic, and
+/// symbolic links are not followed. The `.git` directory is never searched.
+///
+/// Only failing to read `root` itself is an error. Entries beneath it that
+/// can't be read are reported and skipped, as `grep -r` does, and flagged in
+/// the returned bool.
+pub fn walk(
+    root: &Path,
+    filter: &GlobFilter,
+    options: WalkOptions,
+) -> io::Result<(Vec<PathBuf>, bool)> {
+    let walker = Walker::run(root, filter, options)?;
+    Ok((walker.files, walker.errors))
+}
+
+/// Collects `root` and the directories beneath it that `walk` would descend
+/// into, whether or not they hold any selected files.
+pub fn walk_dirs(
+    root: &Path,
+    filter: &GlobFilter,
+    options: WalkOptions,
+) -> io::Result<(Vec<PathBuf>, bool)> {
+    let walker = Walker::run(root, filter, options)?;
+    Ok((walker.dirs, walker.errors))
+}
+
+impl<'a> Walker<'a> {
+    fn run(root: &'a Path, filter: &'a GlobFilter, options: WalkOptions) -> io::Result<Walker<'a>> {
+        let abs_root = fs::canonicalize(root)?;
+        let mut walker = Walker {
+            root,
+            abs_root,
+            filter,
+            options,
+            ignores: Vec::new(),
+            files: Vec::new(),
+            dirs: Vec::new(),
+            errors: false,
+        };
+        let in_repo = options.ignore && walker.load_parent_ignores();
+        walker.walk_dir(Path::new(""), in_repo)?;
+        Ok(walker)
+    }
+
+    /// Rules in the directories between the enclosing git repository's
+    /// top level and the search root still apply to the search root.
+    /// Returns whether there is such a repository.
+    fn load_parent_ignores(&mut self) -> bool {
+        let top = match self.abs_root.ancestors().find(|dir| is_repo_root(dir)) {
+            Some(v) => v,
+            None => return false,
+        };
+        let mut parents: Vec<&Path> = self
+            .abs_root
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(top))
+            .collect();
+        parents.reverse();
+        for dir in parents {
+            if let Some(ignore) = load_ignore_files(dir, true) {
+                self.ignores.push(ignore);
+            }
+        }
+        true
+    }
+
+    /// Walks a directory, `in_repo` telling whether it is inside a git
+    /// repository, either the one enclosing the search root or one found
+    /// beneath it. Errors are only returned for the root.
+    fn walk_dir(&mut self, rel: &Path, in_repo: bool) -> io::Result<()> {
+        let dir = self.root.join(rel);
+        let entries = match fs::read_dir(&dir) {
+            Ok(v) => v,
+            Err(e) if rel.as_os_str().is_empty() => return Err(e),
+            Err(e) => {
+                self.report(&dir, e);
+                return Ok(());
+            }
+        };
+        self.dirs.push(dir.clone());
+        let abs = self.abs_root.join(rel);
+        let in_repo = in_repo || (self.options.ignore && is_repo_root(&abs));
+        let pushed = match self.options.ignore {
+            true => load_ignore_files(&abs, in_repo),
+            false => None,
+        };
+        let pushed = pushed.map(|ignore| self.ignores.push(ignore)).is_some();
+
+        let mut entries: Vec<_> = entries
+            .filter_map(|entry| entry.map_err(|e| self.report(&dir, e)).ok())
+            .collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let name = entry.file_name();
+            if name == ".git" || (!self.options.hidden && name.to_string_lossy().starts_with('.')) {
+                continue;
+            }
+
+            let rel = rel.join(&name);
+            let file_type = match entry.file_type() {
+                Ok(v) => v,
+                Err(e) => {
+                    self.report(&self.root.join(&rel), e);
+                    continue;
+                }
+            };
+            if self.is_ignored(&rel, file_type.is_dir()) {
+                continue;
+            }
+
+            if file_type.is_dir() {
+                if self.filter.is_dir_selected(&rel) {
+                    self.walk_dir(&rel, in_repo)?;
+                }
+            } else if file_type.is_file() && self.filter.is_file_selected(&rel) {
+                self.files.push(self.root.join(&rel));
+            }
+        }
+
+        if pushed {
+            self.ignores.pop();
+        }
+        Ok(())
+    }
+
+    /// Reports an entry that can't be read, for the walk to carry on
+    /// without it.
+    fn report(&mut self, path: &Path, e: io::Error) {
+        eprintln!("{}: {}", path.display(), e);
+        self.errors = true;
+    }
+
+    /// Consults the ignore stack from the innermost directory outwards. The
+    /// first rule that matches, either ignoring or whitelisting, wins.
+    fn is_ignored(&self, rel: &Path, is_dir: bool) -> bool {
+        let path = self.abs_root.join(rel);
+        for ignore in self.ignores.iter().rev() {
+            match ignore.matched(&path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+/// Whether the directory is the top level of a git repository. `.git` is a
+/// file rather than a directory in worktrees and submodules.
+fn is_repo_root(dir: &Path) -> bool {
+    dir.join(".git").exists()
+}
+
+/// Builds the ignore rules defined in a single directory. Files added later
+/// take precedence, so `.ignore` overrides `.gitignore`, which overrides
+/// `.git/info/exclude`. The git files only count inside a repository, and
+/// the exclude file only at its top level.
+fn load_ignore_files(dir: &Path, in_repo: bool) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+    let names: &[&str] = match (in_repo, is_repo_root(dir)) {
+        (true, true) => &[".git/info/exclude", ".gitignore", ".ignore"],
+        (true, false) => &[".gitignore", ".ignore"],
+        (false, _) => &[".ignore"],
+    };
+    for name in names {
+        let path = dir.join(name);
+        if path.is_file() {
+            if let Some(e) = builder.add(&path) {
+                eprintln!("{}: {}", path.display(), e);
+            }
+            found = true;
+        }
+    }
+    if !found {
+        return None;
+    }
+    match builder.build() {
+        Ok(v) => Some(v),
+        Err(e) => {
+            eprintln!("{}: {}", dir.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn tree() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for path in &[
+            "src/lib.rs",
+            "src/main.rs",
+            "Cargo.toml",
+            "target/debug/out.rs",
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    fn relative(root: &Path, files: Vec<PathBuf>) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| f.strip_prefix(root).unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn write(dir: &TempDir, path: &str, contents: &str) {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Makes the directory the top level of a git repository.
+    fn init_repo(dir: &Path) {
+        fs::create_dir_all(dir.join(".git")).unwrap();
+    }
+
+    fn walk_all(dir: &TempDir, options: WalkOptions) -> Vec<String> {
+        let filter = GlobFilter::new(&[], &[]).unwrap();
+        let (files, _) = walk(dir.path(), &filter, options).unwrap();
+        relative(dir.path(), files)
+    }
+
+    #[test]
+    fn walks_everything_by_default() {
+        let dir = tree();
+        let filter = GlobFilter::new(&[], &[]).unwrap();
+        let (files, _) = walk(dir.path(), &filter, WalkOptions::default()).unwrap();
+        assert_eq!(
+            vec![
+                "Cargo.toml",
+                "src/lib.rs",
+                "src/main.rs",
+                "target/debug/out.rs"
+            ],
+            relative(dir.path(), files)
+        );
+    }
+
+    #[test]
+    fn walks_directories() {
+        let dir = tree();
+        write(&dir, ".gitignore", "target\n");
+        fs::create_dir(dir.path().join("empty")).unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        let filter = GlobFilter::new(&[], &[]).unwrap();
+        let (dirs, _) = walk_dirs(dir.path(), &filter, WalkOptions::default()).unwrap();
+        assert_eq!(vec!["", "empty", "src"], relative(dir.path(), dirs));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn carries_on_past_unreadable_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tree();
+        let locked = dir.path().join("target");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        let readable = fs::read_dir(&locked).is_ok();
+        let filter = GlobFilter::new(&[], &[]).unwrap();
+        let walked = walk(dir.path(), &filter, WalkOptions::default());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(walk(&dir.path().join("missing"), &filter, WalkOptions::default()).is_err());
+        // permissions don't keep root out
+        if readable {
+            return;
+        }
+        let (files, errors) = walked.unwrap();
+        assert!(errors);
+        assert_eq!(
+            vec!["Cargo.toml", "src/lib.rs", "src/main.rs"],
+            relative(dir.path(), files)
+        );
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let dir = tree();
+        let filter = GlobFilter::new(&["*.rs".to_string()], &["target/**".to_string()]).unwrap();
+        let (files, _) = walk(dir.path(), &filter, WalkOptions::default()).unwrap();
+        assert_eq!(
+            vec!["src/lib.rs", "src/main.rs"],
+            relative(dir.path(), files)
+        );
+    }
+
+    #[test]
+    fn exclude_by_file_name() {
+        let dir = tree();
+        let filter = GlobFilter::new(&[], &["main.rs".to_string(), "target".to_string()]).unwrap();
+        let (files, _) = walk(dir.path(), &filter, WalkOptions::default()).unwrap();
+        assert_eq!(
+            vec!["Cargo.toml", "src/lib.rs"],
+            relative(dir.path(), files)
+        );
+    }
+
+    #[test]
+    fn honours_gitignore() {
+        let dir = tree();
+        init_repo(dir.path());
+        write(&dir, ".gitignore", "target/\n*.toml\n");
+        write(&dir, "src/.gitignore", "main.rs\n");
+        assert_eq!(vec!["src/lib.rs"], walk_all(&dir, WalkOptions::default()));
+    }
+
+    #[test]
+    fn nested_rules_override_parents() {
+        let dir = tree();
+        init_repo(dir.path());
+        write(&dir, ".gitignore", "*.rs\n");
+        write(&dir, "src/.ignore", "!lib.rs\n");
+        assert_eq!(
+            vec!["Cargo.toml", "src/lib.rs"],
+            walk_all(&dir, WalkOptions::default())
+        );
+    }
+
+    #[test]
+    fn honours_git_info_exclude() {
+        let dir = tree();
+        write(&dir, ".git/info/exclude", "target\nmain.rs\n");
+        assert_eq!(
+            vec!["Cargo.toml", "src/lib.rs"],
+            walk_all(&dir, WalkOptions::default())
+        );
+    }
+
+    #[test]
+    fn gitignore_only_inside_repositories() {
+        let dir = tree();
+        write(&dir, ".gitignore", "target/\n");
+        write(&dir, ".ignore", "*.toml\n");
+        assert_eq!(
+            vec!["src/lib.rs", "src/main.rs", "target/debug/out.rs"],
+            walk_all(&dir, WalkOptions::default())
+        );
+
+        // a repository found beneath the root applies its own rules
+        init_repo(&dir.path().join("target"));
+        write(&dir, "target/.gitignore", "debug\n");
+        write(&dir, "target/.git/info/exclude", "*.rs\n");
+        assert_eq!(
+            vec!["src/lib.rs", "src/main.rs"],
+            walk_all(&dir, WalkOptions::default())
+        );
+    }
+
+    #[test]
+    fn no_ignore() {
+        let dir = tree();
+        write(&dir, ".gitignore", "target/\n");
+        let options = WalkOptions {
+            ignore: false,
+            ..WalkOptions::default()
+        };
+        assert_eq!(
+            vec![
+                "Cargo.toml",
+                "src/lib.rs",
+                "src/main.rs",
+                "target/debug/out.rs"
+            ],
+            walk_all(&dir, options)
+        );
+    }
+
+    #[test]
+    fn hidden_files() {
+        let dir = tree();
+        write(&dir, ".env", "");
+        write(&dir, ".config/settings", "");
+        write(&dir, ".git/HEAD", "");
+        write(&dir, ".gitignore", "target/\n");
+        assert_eq!(
+            vec!["Cargo.toml", "src/lib.rs", "src/main.rs"],
+            walk_all(&dir, WalkOptions::default())
+        );
+
+        let options = WalkOptions {
+            hidden: true,
+            ..WalkOptions::default()
+        };
+        assert_eq!(
+            vec![
+                ".config/settings",
+                ".env",
+                ".gitignore",
+                "Cargo.toml",
+                "src/lib.rs",
+                "src/main.rs"
+            ],
+            walk_all(&dir, options)
+        );
+    }
+}
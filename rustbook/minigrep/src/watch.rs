@@ -0,0 +1,495 @@
+use crate::{
+    collect_files, print_options, search_reader_at, sniff_binary, walk_dirs, Config, GlobFilter,
+    Matcher, Outcome, OutputMode, Position, PrintOptions, Printer, WalkOptions, STDIN_NAME,
+};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use memchr::{memchr_iter, memrchr};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Follows a file as it grows, remembering how much of it has been read.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Tail {
+    position: Position,
+    inode: u64,
+}
+
+impl Tail {
+    /// Hands the complete lines appended since the last call to `search`,
+    /// as a reader over that part of the file along with the position it
+    /// starts at, so that they are streamed rather than loaded. A line still
+    /// being written is left for the next call. A file that shrank or was
+    /// replaced by a new one, as when a log is rotated, is read again from
+    /// the start.
+    pub fn read_new<T, F>(&mut self, path: &Path, search: F) -> io::Result<T>
+    where
+        F: FnOnce(Position, &mut dyn BufRead) -> io::Result<T>,
+    {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        if metadata.ino() != self.inode || metadata.len() < self.position.offset as u64 {
+            *self = Tail {
+                position: Position::default(),
+                inode: metadata.ino(),
+            };
+        }
+
+        let start = self.position.offset as u64;
+        let len = complete_lines(&mut file, start, metadata.len())?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut reader = BufReader::new(CountLines {
+            inner: file.take(len),
+            lines: 0,
+        });
+        let result = search(self.position, &mut reader)?;
+        // whatever the search left unread still counts towards the position
+        io::copy(&mut reader, &mut io::sink())?;
+
+        self.position.offset += len as usize;
+        self.position.lines += reader.get_ref().lines;
+        Ok(result)
+    }
+}
+
+/// The length of the part of the file from `start` to `end` that ends with
+/// its last newline, found by reading backwards from `end`.
+fn complete_lines(file: &mut File, start: u64, end: u64) -> io::Result<u64> {
+    let mut buf = vec![0; 8 * 1024];
+    let mut chunk_end = end;
+    while chunk_end > start {
+        let chunk_start = chunk_end.saturating_sub(buf.len() as u64).max(start);
+        let chunk = &mut buf[..(chunk_end - chunk_start) as usize];
+        file.seek(SeekFrom::Start(chunk_start))?;
+        file.read_exact(chunk)?;
+        if let Some(i) = memrchr(b'\n', chunk) {
+            return Ok(chunk_start + i as u64 + 1 - start);
+        }
+        chunk_end = chunk_start;
+    }
+    Ok(0)
+}
+
+/// Counts the newlines read through it.
+struct CountLines<R> {
+    inner: R,
+    lines: usize,
+}
+
+impl<R: Read> Read for CountLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.lines += memchr_iter(b'\n', &buf[..n]).count();
+        Ok(n)
+    }
+}
+
+/// Events that may mean a file has new contents, or a new file or
+/// directory has appeared.
+fn watch_mask() -> WatchMask {
+    WatchMask::MODIFY
+        | WatchMask::CLOSE_WRITE
+        | WatchMask::CREATE
+        | WatchMask::MOVED_TO
+        | WatchMask::MOVED_FROM
+        | WatchMask::DELETE
+}
+
+struct Watcher<'a> {
+    config: &'a Config,
+    matcher: &'a Matcher,
+    options: PrintOptions,
+    filter: GlobFilter,
+    inotify: Inotify,
+    /// The directory each watch was added for.
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    /// Files named on the command line, whose directories are watched so
+    /// that a rotated file is picked up again.
+    named: HashSet<PathBuf>,
+    /// Directories named on the command line, within which any selected
+    /// file is followed.
+    roots: Vec<PathBuf>,
+    tails: HashMap<PathBuf, Tail>,
+    outcome: Outcome,
+}
+
+/// Searches the files, then keeps watching them with inotify. Lines added to
+/// a file are searched as they are appended, without reading the rest of it
+/// again, and new files in watched directories are searched in full. Each
+/// result found after the first pass is prefixed with the time it was seen.
+///
+/// Ignore files are applied to the files present when watching starts.
+/// Returns only on error, or in quiet mode once a line is selected.
+pub fn watch(
+    config: &Config,
+    matcher: &Matcher,
+    patterns: Vec<String>,
+) -> Result<Outcome, Box<dyn Error>> {
+    if config.paths.is_empty() || config.paths.iter().any(|p| p == STDIN_NAME) {
+        return Err("--watch needs files or directories to watch".into());
+    }
+    let paths: Vec<&Path> = config.paths.iter().map(Path::new).collect();
+    let with_path = paths.len() > 1 || paths.iter().any(|path| path.is_dir());
+    let mut watcher = Watcher {
+        config,
+        matcher,
+        // lines are printed as they arrive, so can't be ranked
+        options: PrintOptions {
+            rank: false,
+            ..print_options(config, patterns, with_path)
+        },
+        filter: GlobFilter::new(&config.include, &config.exclude)?,
+        inotify: Inotify::init()?,
+        dirs: HashMap::new(),
+        named: HashSet::new(),
+        roots: Vec::new(),
+        tails: HashMap::new(),
+        outcome: Outcome::default(),
+    };
+
+    let (files, walk_errors) = collect_files(&paths, config)?;
+    watcher.outcome.errors = walk_errors;
+    for path in &paths {
+        if path.is_dir() {
+            watcher.roots.push(path.to_path_buf());
+            watcher.add_directories(path)?;
+        } else {
+            watcher.named.insert(path.to_path_buf());
+            watcher.add_watch(path.parent().unwrap_or(Path::new("")).to_path_buf())?;
+        }
+    }
+
+    for file in &files {
+        watcher.follow(file, None)?;
+    }
+    loop {
+        if watcher.config.mode == OutputMode::Quiet && watcher.outcome.matched {
+            return Ok(watcher.outcome);
+        }
+        watcher.wait()?;
+    }
+}
+
+impl Watcher<'_> {
+    fn add_watch(&mut self, dir: PathBuf) -> io::Result<()> {
+        let target = match dir.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => dir.as_path(),
+        };
+        let wd = self.inotify.watches().add(target, watch_mask())?;
+        self.dirs.insert(wd, dir);
+        Ok(())
+    }
+
+    /// Blocks until inotify reports changes and handles them.
+    fn wait(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut buffer = [0; 4096];
+        let events: Vec<(WatchDescriptor, EventMask, Option<OsString>)> = self
+            .inotify
+            .read_events_blocking(&mut buffer)?
+            .map(|e| (e.wd, e.mask, e.name.map(OsString::from)))
+            .collect();
+
+        let now = timestamp(SystemTime::now());
+        for (wd, mask, name) in events {
+            let (dir, name) = match (self.dirs.get(&wd), name) {
+                (Some(dir), Some(name)) => (dir, name),
+                _ => continue,
+            };
+            let path = dir.join(name);
+
+            if mask.contains(EventMask::ISDIR) {
+                let created = mask.intersects(EventMask::CREATE | EventMask::MOVED_TO);
+                if created && self.under_root(&path) {
+                    self.add_directory(&path, &now)?;
+                }
+            } else if mask.intersects(EventMask::DELETE | EventMask::MOVED_FROM) {
+                self.tails.remove(&path);
+            } else if self.is_selected(&path) {
+                self.follow(&path, Some(&now))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Watches the directory and those beneath it that aren't ignored.
+    fn add_directories(&mut self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        let options = WalkOptions {
+            hidden: self.config.hidden,
+            ignore: !self.config.no_ignore,
+        };
+        let (dirs, _) = walk_dirs(dir, &self.filter, options)?;
+        for dir in dirs {
+            self.add_watch(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Starts watching a directory created while watching, searching the
+    /// files already written to it.
+    fn add_directory(&mut self, dir: &Path, now: &str) -> Result<(), Box<dyn Error>> {
+        self.add_directories(dir)?;
+        let (files, _) = collect_files(&[dir], self.config)?;
+        for file in files {
+            self.follow(&file, Some(now))?;
+        }
+        Ok(())
+    }
+
+    fn under_root(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+
+    /// Whether a changed file should be searched: one already followed, one
+    /// named on the command line, or a new file in a watched directory that
+    /// passes the include and exclude globs.
+    fn is_selected(&self, path: &Path) -> bool {
+        if self.tails.contains_key(path) || self.named.contains(path) {
+            return true;
+        }
+        self.roots.iter().any(|root| match path.strip_prefix(root) {
+            Ok(relative) => {
+                let hidden = relative
+                    .components()
+                    .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+                (self.config.hidden || !hidden) && self.filter.is_file_selected(relative)
+            }
+            Err(_) => false,
+        })
+    }
+
+    /// Searches what was appended to the file since it was last read and
+    /// prints the results, stamped with `now` when given.
+    fn follow(&mut self, path: &Path, now: Option<&str>) -> io::Result<()> {
+        let (matcher, config) = (self.matcher, self.config);
+        let mut printer = Printer::new(Vec::new(), self.options.clone());
+        let tail = self.tails.entry(path.to_path_buf()).or_default();
+        let searched = tail.read_new(path, |start, reader| {
+            let (binary, reader) = sniff_binary(reader)?;
+            if binary {
+                return Ok(None);
+            }
+            let stats = search_reader_at(
+                matcher,
+                config.invert,
+                config.replace.as_deref(),
+                path,
+                reader,
+                &mut printer,
+                start,
+            )?;
+            Ok(Some(stats))
+        });
+        let stats = match searched {
+            Ok(Some(stats)) => stats,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    eprintln!("{}: {}", path.display(), e);
+                    self.outcome.errors = true;
+                }
+                self.tails.remove(path);
+                return Ok(());
+            }
+        };
+        self.outcome.matched |= stats.matched_lines > 0;
+
+        let output = printer.into_inner();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        match now {
+            Some(now) => stamp(&mut out, &output, now, self.options.json)?,
+            None => out.write_all(&output)?,
+        }
+        out.flush()
+    }
+}
+
+/// Writes the output with each line prefixed by the time, or with the time
+/// added to each record as a `time` field when the output is JSON.
+fn stamp(out: &mut dyn Write, output: &[u8], now: &str, json: bool) -> io::Result<()> {
+    for line in output.split_inclusive(|&b| b == b'\n') {
+        if json {
+            let mut event: serde_json::Value = serde_json::from_slice(line)?;
+            event["time"] = json!(now);
+            writeln!(out, "{}", event)?;
+        } else {
+            write!(out, "[{}] ", now)?;
+            out.write_all(line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Formats the time as an ISO 8601 UTC timestamp with second precision.
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+
+    // civil date from days since the epoch, after Howard Hinnant
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+    use std::time::Duration;
+
+    /// Reads everything `read_new` hands over.
+    fn read(tail: &mut Tail, path: &Path) -> (Position, Vec<u8>) {
+        tail.read_new(path, |start, reader| {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            Ok((start, data))
+        })
+        .unwrap()
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn follows_appended_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.txt");
+        fs::write(&path, "one\ntwo\nthr").unwrap();
+
+        let mut tail = Tail::default();
+        assert_eq!(
+            (Position::default(), b"one\ntwo\n".to_vec()),
+            read(&mut tail, &path)
+        );
+        assert_eq!(
+            (
+                Position {
+                    lines: 2,
+                    offset: 8
+                },
+                Vec::new()
+            ),
+            read(&mut tail, &path)
+        );
+
+        append(&path, "ee\nfour\n");
+        assert_eq!(
+            (
+                Position {
+                    lines: 2,
+                    offset: 8
+                },
+                b"three\nfour\n".to_vec()
+            ),
+            read(&mut tail, &path)
+        );
+    }
+
+    #[test]
+    fn counts_lines_left_unread() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.txt");
+        let long = "x".repeat(20_000);
+        fs::write(&path, format!("one\n{}\ntwo\nthr", long)).unwrap();
+
+        let mut tail = Tail::default();
+        let first = tail
+            .read_new(&path, |_, reader| {
+                let mut line = String::new();
+                reader.read_line(&mut line)?;
+                Ok(line)
+            })
+            .unwrap();
+        assert_eq!("one\n", first);
+
+        append(&path, "ee\n");
+        assert_eq!(
+            (
+                Position {
+                    lines: 3,
+                    offset: 20_009
+                },
+                b"three\n".to_vec()
+            ),
+            read(&mut tail, &path)
+        );
+    }
+
+    #[test]
+    fn rereads_truncated_and_replaced_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.txt");
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let mut tail = Tail::default();
+        read(&mut tail, &path);
+
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!(
+            (Position::default(), b"new\n".to_vec()),
+            read(&mut tail, &path)
+        );
+
+        // rotated: a different file of the same length takes its place
+        let rotated = dir.path().join("log.txt.1");
+        fs::rename(&path, &rotated).unwrap();
+        fs::write(&path, "old\n").unwrap();
+        assert_eq!(
+            (Position::default(), b"old\n".to_vec()),
+            read(&mut tail, &path)
+        );
+    }
+
+    #[test]
+    fn utc_timestamps() {
+        assert_eq!("1970-01-01T00:00:00Z", timestamp(UNIX_EPOCH));
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3_723);
+        assert_eq!("2000-02-29T01:02:03Z", timestamp(leap_day));
+        let later = UNIX_EPOCH + Duration::from_secs(1_792_367_999);
+        assert_eq!("2026-10-18T23:59:59Z", timestamp(later));
+    }
+
+    #[test]
+    fn stamps_lines_and_records() {
+        let now = "2026-10-18T12:00:00Z";
+        let mut out = Vec::new();
+        stamp(&mut out, b"a.txt:1:one\na.txt:2:two\n", now, false).unwrap();
+        assert_eq!(
+            "[2026-10-18T12:00:00Z] a.txt:1:one\n[2026-10-18T12:00:00Z] a.txt:2:two\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = Vec::new();
+        let output = b"{\"type\":\"begin\",\"data\":{\"path\":\"a.txt\"}}\n";
+        stamp(&mut out, output, now, true).unwrap();
+        let event: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            json!({ "type": "begin", "data": { "path": "a.txt" }, "time": now }),
+            event
+        );
+    }
+}
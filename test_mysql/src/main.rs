@@ -1,52 +1,16 @@
 #![feature(generators, generator_trait)]
 
+use candle::{
+    load_candles_direct, parse_candle, stream_candle_cont, stream_candles, Candle, Period,
+};
 use mysql::prelude::*;
 use mysql::*;
-use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::sync::mpsc::sync_channel;
-use std::sync::mpsc::SyncSender;
 use std::thread;
 use toml::Value;
 
-#[derive(Debug)] // enables the ability to use
-pub struct Candle {
-    period: i32,
-    unix: i32,
-    high: f64,
-    low: f64,
-    open: f64,
-    close: f64,
-    volume: f64,
-    quote_volume: f64,
-}
-
-/**
- * Example of adding display to a Candle which can be used with code that looks
- * like:
- *
- * ```rust
- * println!("{0}", selected_candles[0]);
- * ```
- */
-impl fmt::Display for Candle {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "({}, {}, {}, {}, {}, {}, {}, {})",
-            self.period,
-            self.unix,
-            self.open,
-            self.high,
-            self.low,
-            self.close,
-            self.volume,
-            self.quote_volume
-        )
-    }
-}
-
 // use of ? operator for function calls requires us to use -> Result<()>
 fn main() -> Result<()> {
     // open the config file and read the contents
@@ -98,7 +62,7 @@ fn main() -> Result<()> {
     let result_set = result.next_set().unwrap()?;
     let mut i = 0;
     for row in result_set {
-        let _candle = parse_candle(&row?);
+        let _candle = parse_candle(&row?)?;
         i += 1;
     }
     println!("load directly {} candles", i);
@@ -120,7 +84,7 @@ fn main() -> Result<()> {
     thread::sleep(std::time::Duration::from_secs(1));
     let mut i = 0; // this value is in closure scope
     stream_candle_cont(&pool, &mut |candle| {
-        if candle.period == 86400 {
+        if candle.period == Period::D1 {
             println!("{}", candle);
         }
         i += 1; // this is closure scoped
@@ -130,48 +94,6 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn load_candles_direct(pool: &mysql::Pool) -> Result<Vec<Candle>> {
-    let mut conn = pool.get_conn()?;
-    conn.query_map(
-        "select * from candle.binance_btc_usdt",
-        |(period, unix, high, low, open, close, volume, quote_volume)| crate::Candle {
-            period,
-            unix,
-            high,
-            low,
-            open,
-            close,
-            volume,
-            quote_volume,
-        },
-    )
-}
-
-fn stream_candles(pool: &mysql::Pool, sender: &SyncSender<Candle>) -> Result<()> {
-    let mut conn = pool.get_conn()?;
-    let mut result = conn.query_iter("select * from candle.binance_btc_usdt")?;
-
-    let result_set = result.next_set();
-    for row in result_set.unwrap().unwrap() {
-        let candle = parse_candle(&row?);
-        sender.send(candle).unwrap();
-    }
-    Ok(())
-}
-
-fn parse_candle(row: &Row) -> Candle {
-    Candle {
-        period: row.get(0).unwrap_or_default(),
-        unix: row.get(1).unwrap_or_default(),
-        high: row.get(2).unwrap_or_default(),
-        low: row.get(3).unwrap_or_default(),
-        open: row.get(4).unwrap_or_default(),
-        close: row.get(5).unwrap_or_default(),
-        volume: row.get(6).unwrap_or_default(),
-        quote_volume: row.get(7).unwrap_or_default(),
-    }
-}
-
 fn get_conn(pool: &Pool) -> PooledConn {
     pool.get_conn().unwrap()
 }
@@ -184,24 +106,7 @@ fn load_candle_resultset(conn: &mut mysql::PooledConn) -> QueryResult<Text> {
 fn process_result_set(result_set: Result<ResultSet<Text>>) -> Result<Vec<Candle>> {
     let mut vec = Vec::<Candle>::new();
     for row in result_set.unwrap() {
-        vec.push(parse_candle(&row?));
+        vec.push(parse_candle(&row?)?);
     }
     Ok(vec)
 }
-
-// Could also use the method signature:
-// fn stream_candle_cont(pool: &mysql::Pool, on_row: &mut dyn FnMut(Candle)) -> Result<()> {
-fn stream_candle_cont<F>(pool: &mysql::Pool, on_row: &mut F) -> Result<()>
-where
-    F: FnMut(Candle),
-{
-    let mut conn = pool.get_conn()?;
-    let mut result = conn.query_iter("select * from candle.binance_btc_usdt")?;
-
-    let result_set = result.next_set();
-    for row in result_set.unwrap().unwrap() {
-        let candle = parse_candle(&row?);
-        on_row(candle);
-    }
-    Ok(())
-}
@@ -1,3 +1,4 @@
+use candle::Candle;
 use futures::StreamExt;
 use sqlx::mysql::*;
 use sqlx::prelude::*;
@@ -5,11 +6,6 @@ use std::env;
 use tokio::fs::File;
 use tokio::prelude::*;
 
-#[derive(sqlx::FromRow)]
-struct Candle {
-    unix: i32,
-}
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let database_url = &env::var("DATABASE_URL")?;
@@ -45,19 +41,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     //
     let mut stream =
-        sqlx::query_as::<_, Candle>("select unix from candle.binance_btc_usdt").fetch(&pool);
+        sqlx::query_as::<_, Candle>("select * from candle.binance_btc_usdt").fetch(&pool);
     // We need to use futures::StreamExt to use the `next` method again
     while let Some(candle) = stream.next().await {
-        println!("woot {}", candle?.unix);
+        println!("woot {}", candle?.time);
     }
 
     // construct a file and we will stream shit into it!
     let mut file = File::create("foo.txt").await?;
     let mut stream =
-        sqlx::query_as::<_, Candle>("select unix from candle.binance_btc_usdt").fetch(&pool);
+        sqlx::query_as::<_, Candle>("select * from candle.binance_btc_usdt").fetch(&pool);
     while let Some(candle) = stream.next().await {
-        let string: String = format!("wrote {}\n", candle?.unix);
-        file.write(string.as_bytes()).await?;
+        let string: String = format!("wrote {}\n", candle?.time);
+        file.write_all(string.as_bytes()).await?;
     }
 
     // test moving to a new work task where we clone the pool for use
@@ -65,10 +61,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let pool2 = pool.clone();
     let handle = tokio::spawn(async move {
         let mut stream =
-            sqlx::query_as::<_, Candle>("select unix from candle.binance_btc_usdt").fetch(&pool2);
+            sqlx::query_as::<_, Candle>("select * from candle.binance_btc_usdt").fetch(&pool2);
         // We need to use futures::StreamExt to use the `next` method again
         while let Some(candle) = stream.next().await {
-            println!("worker {}", candle.unwrap().unix);
+            println!("worker {}", candle.unwrap().time);
         }
     });
     handle.await?;